@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Sections of an INI file, each mapping keys to raw values.
+pub type Ini = HashMap<String, HashMap<String, String>>;
+
+/// `$XDG_CONFIG_HOME/rust-sweeper`, falling back to `~/.config/rust-sweeper`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("rust-sweeper"))
+}
+
+/// `$XDG_DATA_HOME/rust-sweeper`, falling back to `~/.local/share/rust-sweeper`.
+pub fn data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|dir| dir.join("rust-sweeper"))
+}
+
+/// Parses a minimal INI file: `[section]` headers, `key = value` pairs and
+/// `#` or `;` comments. Keys before the first header go in the "" section.
+pub fn parse_ini(contents: &str) -> Result<Ini, String> {
+    let mut ini = Ini::new();
+    let mut section = String::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((key, value)) => {
+                ini.entry(section.clone()).or_default().insert(
+                    key.trim().to_string(),
+                    value.trim().trim_matches('"').to_string(),
+                );
+            }
+            None => return Err(format!("line {}: expected `key = value`", number + 1)),
+        }
+    }
+
+    Ok(ini)
+}
+
+/// The general settings in `config.ini`, empty if there is none.
+pub fn settings() -> Result<Ini, String> {
+    match config_dir() {
+        Some(dir) => read_ini(&dir.join("config.ini")).map(Option::unwrap_or_default),
+        None => Ok(Ini::new()),
+    }
+}
+
+/// Reads and parses `path`, returning `None` if it does not exist.
+pub fn read_ini(path: &Path) -> Result<Option<Ini>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse_ini(&contents)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// Writes `ini` to `path` with sections and keys sorted, creating the parent
+/// directory if needed.
+pub fn write_ini(path: &Path, ini: &Ini) -> Result<(), String> {
+    let mut sections: Vec<_> = ini.iter().collect();
+    sections.sort_by_key(|(name, _)| name.as_str());
+
+    let mut contents = String::new();
+    for (name, entries) in sections {
+        let mut entries: Vec<_> = entries.iter().collect();
+        entries.sort();
+
+        if !name.is_empty() {
+            contents.push_str(&format!("[{}]\n", name));
+        }
+        for (key, value) in entries {
+            contents.push_str(&format!("{} = {}\n", key, value));
+        }
+        contents.push('\n');
+    }
+
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, contents))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
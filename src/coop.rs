@@ -0,0 +1,597 @@
+//! Co-op play: one server owns the field, any number of clients send their
+//! actions and get back what everyone can see. Lines from the server:
+//!
+//! ```text
+//! welcome <id>
+//! board <rows> <cols> <bombs> <playing|won|lost> <cells>
+//! cursor <id> <row> <col>
+//! left <id>
+//! ```
+//!
+//! and from clients:
+//!
+//! ```text
+//! cursor <row> <col>
+//! reveal <row> <col>
+//! flag <row> <col>
+//! bye
+//! ```
+//!
+//! `cells` lists the tiles row by row: `#` hidden, `A`-`Z` flagged by player
+//! 0-25, `!` flagged by a player who left, `?` question marked, `.` empty, `1`-`8` a number and `*` a bomb.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::field::Field;
+use crate::net::{self, Connection, Wire};
+use crate::sweeper::{GameState, Position, Sweeper};
+use crate::theme::Theme;
+use crate::tile::{Mark, Tile, TileKind};
+
+/// One letter per player for flag ownership.
+pub const MAX_PLAYERS: usize = 26;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisibleCell {
+    Hidden,
+    /// Flagged by this player, or by one who has left.
+    Flag(Option<usize>),
+    Question,
+    Revealed(TileKind),
+}
+
+/// The field as the players see it: no hidden bomb ever leaves the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisibleBoard {
+    pub bombs: usize,
+    pub state: GameState,
+    pub cells: Vec<Vec<VisibleCell>>,
+}
+
+fn state_name(state: GameState) -> &'static str {
+    match state {
+        GameState::Playing => "playing",
+        GameState::Won => "won",
+        GameState::Lost => "lost",
+    }
+}
+
+impl VisibleBoard {
+    pub fn from_field(
+        field: &Field,
+        state: GameState,
+        flag_owners: &HashMap<(usize, usize), usize>,
+    ) -> Self {
+        Self {
+            bombs: field.bombs(),
+            state,
+            cells: field
+                .tile_matrix
+                .iter()
+                .map(|tiles| {
+                    tiles
+                        .iter()
+                        .map(|tile| match (tile.revealed, tile.mark) {
+                            (true, _) => VisibleCell::Revealed(tile.kind),
+                            (false, Mark::Flag(_)) => VisibleCell::Flag(
+                                flag_owners
+                                    .get(&(tile.position.0, tile.position.1))
+                                    .copied(),
+                            ),
+                            (false, Mark::Question) => VisibleCell::Question,
+                            (false, Mark::None) => VisibleCell::Hidden,
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// A field that looks like this board. Hidden tiles are empty, except
+    /// on a cleared board where they can only be bombs.
+    pub fn to_field(&self) -> Field {
+        let hidden = match self.state {
+            GameState::Won => TileKind::Bomb(1),
+            _ => TileKind::Empty,
+        };
+        let tiles = self
+            .cells
+            .iter()
+            .enumerate()
+            .map(|(row, cells)| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .map(|(col, cell)| {
+                        let tile = Tile::new_empty(Position(row, col, 0));
+                        match cell {
+                            VisibleCell::Hidden => Tile {
+                                kind: hidden,
+                                ..tile
+                            },
+                            VisibleCell::Flag(_) => Tile {
+                                kind: hidden,
+                                ..tile
+                            }
+                            .flag(),
+                            VisibleCell::Question => Tile {
+                                kind: hidden,
+                                ..tile
+                            }
+                            .with_mark(Mark::Question),
+                            VisibleCell::Revealed(kind) => Tile {
+                                kind: *kind,
+                                ..tile
+                            }
+                            .reveal(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Field::from_tiles(tiles, self.bombs)
+    }
+
+    /// Who flagged each flagged tile.
+    pub fn flag_owners(&self) -> HashMap<(usize, usize), usize> {
+        self.cells
+            .iter()
+            .enumerate()
+            .flat_map(|(row, cells)| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(col, cell)| match cell {
+                        VisibleCell::Flag(owner) => owner.map(|owner| ((row, col), owner)),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    /// `<rows> <cols> <bombs> <state> <cells>`
+    pub fn encode(&self) -> String {
+        let cells: String = self
+            .cells
+            .iter()
+            .flatten()
+            .map(|cell| match cell {
+                VisibleCell::Hidden => '#',
+                VisibleCell::Flag(Some(owner)) => (b'A' + *owner as u8) as char,
+                VisibleCell::Flag(None) => '!',
+                VisibleCell::Question => '?',
+                VisibleCell::Revealed(TileKind::Empty) => '.',
+                VisibleCell::Revealed(TileKind::Safe(bombs)) => (b'0' + bombs) as char,
+                VisibleCell::Revealed(TileKind::Bomb(_)) => '*',
+            })
+            .collect();
+
+        format!(
+            "{} {} {} {} {}",
+            self.cells.len(),
+            self.cells.first().map_or(0, Vec::len),
+            self.bombs,
+            state_name(self.state),
+            cells
+        )
+    }
+
+    pub fn decode(words: &[&str]) -> std::result::Result<Self, String> {
+        let number = |index: usize| -> std::result::Result<usize, String> {
+            words
+                .get(index)
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| format!("bad board `{}`", words.join(" ")))
+        };
+        let (rows, cols, bombs) = (number(0)?, number(1)?, number(2)?);
+        let state = match words.get(3) {
+            Some(&"playing") => GameState::Playing,
+            Some(&"won") => GameState::Won,
+            Some(&"lost") => GameState::Lost,
+            _ => return Err(format!("bad board state in `{}`", words.join(" "))),
+        };
+        let cells = words
+            .get(4)
+            .map(|cells| cells.as_bytes())
+            .filter(|cells| rows > 0 && cols > 0 && cells.len() == rows * cols)
+            .ok_or_else(|| format!("expected {} cells in `{}`", rows * cols, words.join(" ")))?;
+
+        let cells = cells
+            .chunks(cols)
+            .map(|row| {
+                row.iter()
+                    .map(|&cell| match cell {
+                        b'#' => Ok(VisibleCell::Hidden),
+                        b'A'..=b'Z' => Ok(VisibleCell::Flag(Some((cell - b'A') as usize))),
+                        b'!' => Ok(VisibleCell::Flag(None)),
+                        b'?' => Ok(VisibleCell::Question),
+                        b'.' => Ok(VisibleCell::Revealed(TileKind::Empty)),
+                        b'1'..=b'8' => Ok(VisibleCell::Revealed(TileKind::Safe(cell - b'0'))),
+                        b'*' => Ok(VisibleCell::Revealed(TileKind::Bomb(1))),
+                        _ => Err(format!("bad cell `{}`", cell as char)),
+                    })
+                    .collect()
+            })
+            .collect::<std::result::Result<_, String>>()?;
+
+        Ok(Self {
+            bombs,
+            state,
+            cells,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerMessage {
+    Welcome {
+        id: usize,
+    },
+    Board(VisibleBoard),
+    Cursor {
+        id: usize,
+        position: Position,
+    },
+    Left {
+        id: usize,
+    },
+    /// The server went away.
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    Cursor(Position),
+    Reveal(Position),
+    Flag(Position),
+    Bye,
+}
+
+fn parse_number(words: &[&str], index: usize, line: &str) -> std::result::Result<usize, String> {
+    words
+        .get(index)
+        .and_then(|word| word.parse().ok())
+        .ok_or_else(|| format!("bad message `{}`", line.trim()))
+}
+
+impl Wire for ServerMessage {
+    fn to_line(&self) -> String {
+        match self {
+            ServerMessage::Welcome { id } => format!("welcome {}\n", id),
+            ServerMessage::Board(board) => format!("board {}\n", board.encode()),
+            ServerMessage::Cursor { id, position } => {
+                format!("cursor {} {} {}\n", id, position.0, position.1)
+            }
+            ServerMessage::Left { id } => format!("left {}\n", id),
+            ServerMessage::Closed => String::new(),
+        }
+    }
+
+    fn parse(line: &str) -> std::result::Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |index| parse_number(&words, index, line);
+
+        match words.first() {
+            Some(&"welcome") => Ok(ServerMessage::Welcome { id: number(1)? }),
+            Some(&"board") => VisibleBoard::decode(&words[1..]).map(ServerMessage::Board),
+            Some(&"cursor") => Ok(ServerMessage::Cursor {
+                id: number(1)?,
+                position: Position(number(2)?, number(3)?, 0),
+            }),
+            Some(&"left") => Ok(ServerMessage::Left { id: number(1)? }),
+            _ => Err(format!("bad message `{}`", line.trim())),
+        }
+    }
+
+    fn closed() -> Self {
+        ServerMessage::Closed
+    }
+}
+
+impl Wire for ClientMessage {
+    fn to_line(&self) -> String {
+        match self {
+            ClientMessage::Cursor(Position(row, col, _)) => format!("cursor {} {}\n", row, col),
+            ClientMessage::Reveal(Position(row, col, _)) => format!("reveal {} {}\n", row, col),
+            ClientMessage::Flag(Position(row, col, _)) => format!("flag {} {}\n", row, col),
+            ClientMessage::Bye => "bye\n".to_string(),
+        }
+    }
+
+    fn parse(line: &str) -> std::result::Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let position = || -> std::result::Result<Position, String> {
+            Ok(Position(
+                parse_number(&words, 1, line)?,
+                parse_number(&words, 2, line)?,
+                0,
+            ))
+        };
+
+        match words.first() {
+            Some(&"cursor") => position().map(ClientMessage::Cursor),
+            Some(&"reveal") => position().map(ClientMessage::Reveal),
+            Some(&"flag") => position().map(ClientMessage::Flag),
+            Some(&"bye") => Ok(ClientMessage::Bye),
+            _ => Err(format!("bad message `{}`", line.trim())),
+        }
+    }
+
+    fn closed() -> Self {
+        ClientMessage::Bye
+    }
+}
+
+#[derive(Debug)]
+struct Player {
+    connection: Connection<ServerMessage, ClientMessage>,
+    cursor: Position,
+}
+
+/// Owns the field of a co-op game and applies the players' actions to it.
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    sweeper: Sweeper,
+    players: BTreeMap<usize, Player>,
+    flag_owners: HashMap<(usize, usize), usize>,
+}
+
+impl Server {
+    pub fn new(listener: TcpListener, sweeper: Sweeper) -> Result<Self> {
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            sweeper,
+            players: BTreeMap::new(),
+            flag_owners: HashMap::new(),
+        })
+    }
+
+    pub fn board(&self) -> VisibleBoard {
+        VisibleBoard::from_field(&self.sweeper.field, self.sweeper.state(), &self.flag_owners)
+    }
+
+    fn broadcast(&mut self, message: &ServerMessage) {
+        // A player whose connection broke is removed on its next poll.
+        for player in self.players.values_mut() {
+            let _ = player.connection.send(message);
+        }
+    }
+
+    fn accept(&mut self) -> Result<()> {
+        while let Ok((stream, _)) = self.listener.accept() {
+            let id = match (0..MAX_PLAYERS).find(|id| !self.players.contains_key(id)) {
+                Some(id) => id,
+                None => continue,
+            };
+            stream.set_nonblocking(true)?;
+
+            let mut connection = Connection::new(stream)?;
+            let cursor = Position(self.sweeper.field.rows / 2, self.sweeper.field.cols / 2, 0);
+            let _ = connection.send(&ServerMessage::Welcome { id });
+            let _ = connection.send(&ServerMessage::Board(self.board()));
+            for (other, player) in &self.players {
+                let _ = connection.send(&ServerMessage::Cursor {
+                    id: *other,
+                    position: player.cursor.clone(),
+                });
+            }
+
+            self.players.insert(
+                id,
+                Player {
+                    connection,
+                    cursor: cursor.clone(),
+                },
+            );
+            self.broadcast(&ServerMessage::Cursor {
+                id,
+                position: cursor,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Applies one action of player `id`, returning whether the board changed.
+    fn apply(&mut self, id: usize, message: ClientMessage) -> bool {
+        let field = &self.sweeper.field;
+        let in_bounds = |Position(row, col, _): &Position| *row < field.rows && *col < field.cols;
+        let playing = self.sweeper.state() == GameState::Playing;
+
+        match message {
+            ClientMessage::Cursor(position) if in_bounds(&position) => {
+                if let Some(player) = self.players.get_mut(&id) {
+                    player.cursor = position.clone();
+                }
+                self.broadcast(&ServerMessage::Cursor { id, position });
+                false
+            }
+            ClientMessage::Reveal(position) if playing && in_bounds(&position) => {
+                self.sweeper.reveal(&position);
+                if self.sweeper.state() == GameState::Lost {
+                    self.flag_owners.clear();
+                }
+                true
+            }
+            ClientMessage::Flag(position) if playing && in_bounds(&position) => {
+                let Position(row, col, _) = position;
+                if self.sweeper.field.tile_matrix[row][col].revealed {
+                    return false;
+                }
+
+                // Only whoever marked a tile may move its mark on.
+                if let Some(owner) = self.flag_owners.get(&(row, col)) {
+                    if *owner != id {
+                        return false;
+                    }
+                }
+                self.sweeper.field = self.sweeper.field.cycle_mark(&position);
+                match self.sweeper.field.tile_matrix[row][col].mark {
+                    Mark::None => self.flag_owners.remove(&(row, col)),
+                    Mark::Flag(_) | Mark::Question => self.flag_owners.insert((row, col), id),
+                };
+                true
+            }
+            ClientMessage::Bye => {
+                self.players.remove(&id);
+                self.broadcast(&ServerMessage::Left { id });
+                // The flags stay, but anyone may move them on now, and
+                // whoever takes the id next does not inherit them.
+                let owned = self.flag_owners.len();
+                self.flag_owners.retain(|_, owner| *owner != id);
+                self.flag_owners.len() != owned
+            }
+            _ => false,
+        }
+    }
+
+    /// Accepts new players and applies everything sent since the last step.
+    /// Actions are applied by player id, then in the order each player sent
+    /// them, so actions arriving together always resolve the same way.
+    pub fn step(&mut self) -> Result<()> {
+        self.accept()?;
+
+        let mut actions = vec![];
+        for (id, player) in self.players.iter_mut() {
+            loop {
+                match player.connection.poll() {
+                    Ok(Some(ClientMessage::Bye)) | Err(_) => {
+                        actions.push((*id, ClientMessage::Bye));
+                        break;
+                    }
+                    Ok(Some(message)) => actions.push((*id, message)),
+                    Ok(None) => break,
+                }
+            }
+        }
+
+        let mut changed = false;
+        for (id, message) in actions {
+            changed |= self.apply(id, message);
+        }
+        if changed {
+            self.broadcast(&ServerMessage::Board(self.board()));
+        }
+
+        Ok(())
+    }
+
+    /// Serves the game until the process is stopped.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            self.step()?;
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// A player's view of a co-op game.
+#[derive(Debug)]
+pub struct Client {
+    connection: Connection<ClientMessage, ServerMessage>,
+    pub id: Option<usize>,
+    pub board: Option<VisibleBoard>,
+    pub cursors: BTreeMap<usize, Position>,
+    pub closed: bool,
+}
+
+impl Client {
+    /// Connects without waiting, `poll` until `id` and `board` are known.
+    pub fn connect(addr: &str) -> Result<Self> {
+        let connection = Connection::new(net::connect(addr)?)?;
+        connection.set_nonblocking()?;
+
+        Ok(Self {
+            connection,
+            id: None,
+            board: None,
+            cursors: BTreeMap::new(),
+            closed: false,
+        })
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) -> Result<()> {
+        Ok(self.connection.send(message)?)
+    }
+
+    /// Applies everything the server sent, returning whether anything changed.
+    pub fn poll(&mut self) -> Result<bool> {
+        let mut changed = false;
+
+        while let Some(message) = self.connection.poll()? {
+            match message {
+                ServerMessage::Welcome { id } => self.id = Some(id),
+                ServerMessage::Board(board) => self.board = Some(board),
+                ServerMessage::Cursor { id, position } => {
+                    self.cursors.insert(id, position);
+                }
+                ServerMessage::Left { id } => {
+                    self.cursors.remove(&id);
+                }
+                ServerMessage::Closed => self.closed = true,
+            }
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    /// Everything needed to draw the other players, once connected.
+    pub fn overlay(&self) -> Option<Overlay> {
+        Some(Overlay {
+            me: self.id?,
+            cursors: self.cursors.clone(),
+            flag_owners: self.board.as_ref()?.flag_owners(),
+        })
+    }
+}
+
+/// The other players' cursors and who owns which flag, drawn on top of the
+/// field by `Sweeper::display_field`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    pub me: usize,
+    pub cursors: BTreeMap<usize, Position>,
+    pub flag_owners: HashMap<(usize, usize), usize>,
+}
+
+impl Overlay {
+    /// The player's own cursor in `[ ]`, the others' in `( )`, both in the
+    /// player's colour, and flags in the colour of whoever placed them.
+    pub fn render_tile(&self, tile: &Tile, theme: &Theme) -> String {
+        let Position(row, col, _) = tile.position;
+        let other_cursor = self
+            .cursors
+            .iter()
+            .find(|(id, position)| **id != self.me && **position == tile.position);
+        let repr = match (tile.is_flagged(), self.flag_owners.get(&(row, col))) {
+            (true, Some(owner)) => format!(
+                "{}{}{}",
+                theme.background(),
+                theme.player(*owner),
+                theme.glyphs.flag()
+            ),
+            _ => tile.repr(theme),
+        };
+
+        match (tile.selected, other_cursor) {
+            (true, _) => {
+                let colour = theme.player(self.me);
+                format!("{}[{}{}]", colour, repr, colour)
+            }
+            (false, Some((id, _))) => {
+                let colour = theme.player(*id);
+                format!("{}({}{})", colour, repr, colour)
+            }
+            (false, None) => format!(" {} ", repr),
+        }
+    }
+}
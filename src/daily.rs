@@ -0,0 +1,122 @@
+use std::fmt;
+
+use crate::sweeper::{Difficulty, Position};
+
+/// Every daily board is a no-guess board of this difficulty.
+pub const DIFFICULTY: Difficulty = Difficulty::Medium;
+
+/// A day in the proleptic Gregorian calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Date {
+    /// The current date in the local time zone.
+    pub fn today() -> Self {
+        let mut tm = std::mem::MaybeUninit::<libc::tm>::zeroed();
+        let tm = unsafe {
+            let now = libc::time(std::ptr::null_mut());
+            libc::localtime_r(&now, tm.as_mut_ptr());
+            tm.assume_init()
+        };
+
+        Self {
+            year: tm.tm_year as i64 + 1900,
+            month: tm.tm_mon as u32 + 1,
+            day: tm.tm_mday as u32,
+        }
+    }
+
+    /// Days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    pub fn days(&self) -> i64 {
+        let year = match self.month <= 2 {
+            true => self.year - 1,
+            false => self.year,
+        };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = (self.month as i64 + 9) % 12;
+        let day_of_year = (153 * month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146097 + day_of_era - 719468
+    }
+
+    /// The inverse of `days`.
+    pub fn from_days(days: i64) -> Self {
+        let days = days + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+        let month = match month < 10 {
+            true => month + 3,
+            false => month - 9,
+        } as u32;
+
+        Self {
+            year: year_of_era + era * 400 + (month <= 2) as i64,
+            month,
+            day,
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        Self::from_days(self.days() - 1)
+    }
+
+    /// The same on every machine, unlike the standard library's hashers.
+    pub fn seed(&self) -> u64 {
+        // splitmix64
+        let mut z = (self.days() as u64).wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Where the day's game is opened, so the first click cannot differ.
+    pub fn first_click(&self, rows: usize, cols: usize) -> Position {
+        let seed = self.seed();
+        Position(
+            (seed >> 32) as usize % rows,
+            (seed & 0xffff_ffff) as usize % cols,
+            0,
+        )
+    }
+
+    /// The key of this date's results in the stats file.
+    pub fn stats_key(&self) -> String {
+        format!("daily {}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dates() {
+        let date = Date {
+            year: 2024,
+            month: 3,
+            day: 1,
+        };
+        assert_eq!(date.days(), 19783);
+        assert_eq!(Date::from_days(date.days()), date);
+        assert_eq!(date.previous().to_string(), "2024-02-29");
+        assert_eq!(Date::from_days(0).to_string(), "1970-01-01");
+        assert_ne!(date.seed(), date.previous().seed());
+    }
+}
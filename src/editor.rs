@@ -0,0 +1,200 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use termion::event::Key;
+
+use crate::field::Field;
+use crate::keymap::{Action, Keymap};
+use crate::layout;
+use crate::renderer::{Cell, Renderer};
+use crate::sweeper::{Difficulty, Position, Sweeper};
+use crate::theme::Theme;
+
+#[derive(Debug, Clone)]
+pub enum EditorChoice {
+    /// Try the board out, coming back to the editor afterwards.
+    Play(Field),
+    Quit,
+}
+
+/// Places bombs on a board by hand and saves it as a `layout`. The board is
+/// shown as it starts, with the bombs uncovered and the numbers of
+/// pre-revealed tiles kept up to date.
+#[derive(Debug)]
+pub struct Editor {
+    /// Only used to move the cursor around and scroll, its tiles are the
+    /// board being edited.
+    sweeper: Sweeper,
+    cursor: Position,
+    path: PathBuf,
+    keymap: Keymap,
+    theme: Theme,
+    renderer: Renderer,
+    modified: bool,
+    /// Quit was pressed with unsaved changes.
+    quitting: bool,
+    /// Shown below the board until the next key.
+    message: Option<String>,
+}
+
+impl Editor {
+    pub fn new(field: Field, path: PathBuf, keymap: Keymap, theme: Theme) -> Self {
+        let cursor = Position(field.rows / 2, field.cols / 2, 0);
+        let mut sweeper =
+            Sweeper::from_field(Difficulty::Custom, field).with_keymap(keymap.clone());
+        sweeper.select(&cursor);
+
+        Self {
+            sweeper,
+            cursor,
+            path,
+            keymap,
+            theme,
+            renderer: Renderer::default(),
+            modified: false,
+            quitting: false,
+            message: None,
+        }
+    }
+
+    pub fn field(&self) -> &Field {
+        &self.sweeper.field
+    }
+
+    pub fn resize(&mut self, size: (u16, u16)) {
+        self.sweeper.resize(size);
+        self.sweeper.select(&self.cursor);
+        self.renderer.invalidate();
+    }
+
+    /// Draws the whole board on the next `display`, e.g. after a test game.
+    pub fn redraw(&mut self) {
+        self.renderer.invalidate();
+    }
+
+    /// Reveal and flag toggle a bomb, `o` toggles a pre-revealed tile, enter
+    /// plays the board, `Ctrl+S` saves it and quitting with unsaved changes
+    /// takes a second press.
+    pub fn tick(&mut self, key: &Key) -> Option<EditorChoice> {
+        let quitting = self.quitting;
+        self.quitting = false;
+        self.message = None;
+
+        match self.keymap.action(key) {
+            Some(Action::Play) => return Some(EditorChoice::Play(self.field().clone())),
+            Some(Action::Save) => {
+                self.message = Some(match layout::save(&self.path, self.field()) {
+                    Ok(()) => {
+                        self.modified = false;
+                        format!("saved {}", self.path.display())
+                    }
+                    Err(e) => format!("cannot save: {}", e),
+                });
+            }
+            Some(Action::Quit) => match self.modified && !quitting {
+                true => {
+                    self.quitting = true;
+                    self.message = Some(format!(
+                        "unsaved changes, {} again to quit",
+                        self.keymap.key_hint(Action::Quit)
+                    ));
+                }
+                false => return Some(EditorChoice::Quit),
+            },
+            Some(Action::Reveal) | Some(Action::Flag) => {
+                self.sweeper.field = self.field().toggle_bomb(&self.cursor);
+                self.sweeper.select(&self.cursor);
+                self.modified = true;
+            }
+            Some(Action::TogglePreRevealed) => {
+                self.sweeper.field = self.field().toggle_revealed(&self.cursor);
+                self.sweeper.select(&self.cursor);
+                self.modified = true;
+            }
+            Some(Action::Restart) => {}
+            _ => self.cursor = self.sweeper.tick(key, self.cursor.clone()).2,
+        }
+
+        None
+    }
+
+    fn status_line(&self) -> Cell {
+        let status = match &self.message {
+            Some(message) => message.clone(),
+            None => format!(
+                "{} bombs  {}{}  {}: toggle bomb, {}: pre-reveal, {}: play, {}: save, {}: quit",
+                self.field().bombs(),
+                self.path.display(),
+                match self.modified {
+                    true => " (modified)",
+                    false => "",
+                },
+                self.keymap.key_hint(Action::Reveal),
+                self.keymap.key_hint(Action::TogglePreRevealed),
+                self.keymap.key_hint(Action::Play),
+                self.keymap.key_hint(Action::Save),
+                self.keymap.key_hint(Action::Quit)
+            ),
+        };
+
+        Cell::new(
+            status.chars().count() + 1,
+            format!("{} {}", self.theme.text(), status),
+        )
+    }
+
+    pub fn display<W: Write>(&mut self, stdout: &mut W) -> io::Result<()> {
+        let mut frame = self
+            .field()
+            .render_with(
+                &self.sweeper.viewport,
+                &self.theme,
+                &|tile| match tile.is_bomb() {
+                    true => Field::render_tile(&tile.reveal(), &self.theme),
+                    false => Field::render_tile(tile, &self.theme),
+                },
+            );
+        frame.push(vec![self.status_line()]);
+
+        match self.theme.is_plain() {
+            true => Renderer::draw_plain(stdout, frame),
+            false => self.renderer.draw(stdout, frame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tile::TileKind;
+
+    use super::*;
+
+    #[test]
+    fn test_editor_toggles_bombs() {
+        let field = Field::from_bombs(3, 3, &[]);
+        let mut editor = Editor::new(field, PathBuf::new(), Keymap::default(), Theme::ascii());
+
+        for key in [Key::Char(' '), Key::Char('d'), Key::Char('f')] {
+            assert!(editor.tick(&key).is_none());
+        }
+        assert_eq!(editor.field().bombs(), 2);
+        assert_eq!(editor.field().tile_matrix[0][1].kind, TileKind::Safe(2));
+
+        editor.tick(&Key::Char('f'));
+        assert_eq!(editor.field().tile_matrix[0][1].kind, TileKind::Safe(1));
+        assert_eq!(layout::format(editor.field()), "...\n.*.\n...\n");
+
+        // Bombs cannot be pre-revealed, safe tiles can.
+        editor.tick(&Key::Char('o'));
+        editor.tick(&Key::Char('a'));
+        editor.tick(&Key::Char('o'));
+        assert_eq!(layout::format(editor.field()), "...\n.*o\n...\n");
+
+        // Unsaved changes need a second quit.
+        assert!(editor.tick(&Key::Char('q')).is_none());
+        assert!(matches!(
+            editor.tick(&Key::Char('q')),
+            Some(EditorChoice::Quit)
+        ));
+    }
+}
@@ -0,0 +1,503 @@
+//! Endless mode: a board without edges, stored in square chunks that are
+//! dealt from the seed only once the cursor gets near them. A chunk's mines
+//! depend on nothing but the seed and its coordinates, so the same seed
+//! always gives the same map, whatever order it is explored in.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use termion::event::Key;
+
+use crate::field::Field;
+use crate::keymap::{Action, Keymap};
+use crate::renderer::{Cell, Frame, Renderer};
+use crate::sweeper::{find_after, CountPrefix, CursorDirection, CursorJump, Motion, Position};
+use crate::theme::Theme;
+use crate::tile::{Mark, Tile, TileKind};
+use crate::viewport::TILE_WIDTH;
+
+/// Tiles along each side of a chunk.
+pub const CHUNK_SIZE: i64 = 16;
+
+const CHUNK_AREA: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Share of tiles holding a mine unless `--bomb-percentile` says otherwise.
+pub const DEFAULT_DENSITY: f32 = 0.15;
+
+/// Tiles opened by one reveal at most, the rest of a large empty area is
+/// left for the next one.
+const FLOOD_LIMIT: usize = 4096;
+
+/// A tile on the endless board, as `(row, col)` from the starting tile.
+pub type Point = (i64, i64);
+
+#[derive(Debug, Clone)]
+struct Chunk {
+    mines: [bool; CHUNK_AREA],
+    revealed: [bool; CHUNK_AREA],
+    marks: [Mark; CHUNK_AREA],
+}
+
+fn chunk_key((row, col): Point) -> (Point, usize) {
+    let index = row.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + col.rem_euclid(CHUNK_SIZE);
+    (
+        (row.div_euclid(CHUNK_SIZE), col.div_euclid(CHUNK_SIZE)),
+        index as usize,
+    )
+}
+
+fn neighbours((row, col): Point) -> impl Iterator<Item = Point> {
+    (row - 1..=row + 1)
+        .flat_map(move |r| (col - 1..=col + 1).map(move |c| (r, c)))
+        .filter(move |&point| point != (row, col))
+}
+
+/// SplitMix64, to spread a chunk's coordinates over the whole seed space.
+fn mix(x: u64) -> u64 {
+    let z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The lazily dealt chunks of an endless board and what the player did on
+/// them.
+#[derive(Debug, Clone)]
+pub struct ChunkStore {
+    seed: u64,
+    density: f32,
+    chunks: HashMap<Point, Chunk>,
+}
+
+impl ChunkStore {
+    pub fn new(seed: u64, density: f32) -> Self {
+        Self {
+            seed,
+            density,
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// Deals the chunk at `key`. The 3x3 area around the starting tile is
+    /// always safe.
+    fn deal(&self, key: Point) -> Chunk {
+        let mut rng = ChaCha8Rng::seed_from_u64(mix(mix(self.seed ^ key.0 as u64) ^ key.1 as u64));
+        let mut mines = [false; CHUNK_AREA];
+
+        for (index, mine) in mines.iter_mut().enumerate() {
+            let row = key.0 * CHUNK_SIZE + index as i64 / CHUNK_SIZE;
+            let col = key.1 * CHUNK_SIZE + index as i64 % CHUNK_SIZE;
+            let roll = rng.gen_range(0.0..1.0) < self.density;
+            *mine = roll && (row.abs() > 1 || col.abs() > 1);
+        }
+
+        Chunk {
+            mines,
+            revealed: [false; CHUNK_AREA],
+            marks: [Mark::None; CHUNK_AREA],
+        }
+    }
+
+    fn chunk(&mut self, point: Point) -> (&mut Chunk, usize) {
+        let (key, index) = chunk_key(point);
+        if !self.chunks.contains_key(&key) {
+            let chunk = self.deal(key);
+            self.chunks.insert(key, chunk);
+        }
+        (self.chunks.get_mut(&key).unwrap(), index)
+    }
+
+    /// Deals every chunk overlapping the `rows` x `cols` area at `top_left`.
+    pub fn deal_around(&mut self, (top, left): Point, rows: i64, cols: i64) {
+        for row in (top.div_euclid(CHUNK_SIZE)..=(top + rows).div_euclid(CHUNK_SIZE))
+            .map(|r| r * CHUNK_SIZE)
+        {
+            for col in (left.div_euclid(CHUNK_SIZE)..=(left + cols).div_euclid(CHUNK_SIZE))
+                .map(|c| c * CHUNK_SIZE)
+            {
+                self.chunk((row, col));
+            }
+        }
+    }
+
+    pub fn is_mine(&mut self, point: Point) -> bool {
+        let (chunk, index) = self.chunk(point);
+        chunk.mines[index]
+    }
+
+    pub fn is_revealed(&mut self, point: Point) -> bool {
+        let (chunk, index) = self.chunk(point);
+        chunk.revealed[index]
+    }
+
+    pub fn mark(&mut self, point: Point) -> Mark {
+        let (chunk, index) = self.chunk(point);
+        chunk.marks[index]
+    }
+
+    /// Neither revealed nor flagged, what the cursor's searches look for.
+    pub fn is_hidden(&mut self, point: Point) -> bool {
+        !self.is_revealed(point) && !matches!(self.mark(point), Mark::Flag(_))
+    }
+
+    /// Mines around `point`, dealing the chunks next to it if needed.
+    pub fn number(&mut self, point: Point) -> u8 {
+        neighbours(point).filter(|&p| self.is_mine(p)).count() as u8
+    }
+
+    fn kind(&mut self, point: Point) -> TileKind {
+        match (self.is_mine(point), self.number(point)) {
+            (true, _) => TileKind::Bomb(1),
+            (false, 0) => TileKind::Empty,
+            (false, bombs) => TileKind::Safe(bombs),
+        }
+    }
+
+    pub fn cycle_mark(&mut self, point: Point) {
+        let (chunk, index) = self.chunk(point);
+        if !chunk.revealed[index] {
+            chunk.marks[index] = chunk.marks[index].next(1);
+        }
+    }
+
+    /// Reveals `point`, flooding through tiles without mines around them.
+    /// Returns how many safe tiles were revealed, or `None` on a mine.
+    pub fn reveal(&mut self, point: Point) -> Option<usize> {
+        let mut stack = vec![point];
+        let mut revealed = 0;
+
+        while let Some(point) = stack.pop() {
+            let (chunk, index) = self.chunk(point);
+            if chunk.revealed[index] || matches!(chunk.marks[index], Mark::Flag(_)) {
+                continue;
+            }
+            chunk.revealed[index] = true;
+            if chunk.mines[index] {
+                return None;
+            }
+
+            revealed += 1;
+            if revealed < FLOOD_LIMIT && self.number(point) == 0 {
+                stack.extend(neighbours(point));
+            }
+        }
+
+        Some(revealed)
+    }
+
+    /// Reveals the unflagged neighbours of a revealed number once as many of
+    /// them are flagged.
+    pub fn chord(&mut self, point: Point) -> Option<usize> {
+        let flags: u8 = neighbours(point)
+            .map(|p| match self.mark(p) {
+                Mark::Flag(flags) => flags,
+                _ => 0,
+            })
+            .sum();
+        if !self.is_revealed(point) || flags != self.number(point) || flags == 0 {
+            return Some(0);
+        }
+
+        neighbours(point).try_fold(0, |revealed, p| Some(revealed + self.reveal(p)?))
+    }
+}
+
+/// A game of endless mode, scored by the safe tiles revealed before the
+/// first mine.
+#[derive(Debug)]
+pub struct Endless {
+    store: ChunkStore,
+    cursor: Point,
+    /// The tile in the top left corner of the screen.
+    top_left: Point,
+    size: (u16, u16),
+    rows: i64,
+    cols: i64,
+    keymap: Keymap,
+    theme: Theme,
+    renderer: Renderer,
+    count: CountPrefix,
+    cleared: usize,
+    /// The mine that ended the game.
+    exploded: Option<Point>,
+}
+
+impl Endless {
+    pub fn new(seed: u64, density: f32, keymap: Keymap, theme: Theme, size: (u16, u16)) -> Self {
+        let mut endless = Self {
+            store: ChunkStore::new(seed, density),
+            cursor: (0, 0),
+            top_left: (0, 0),
+            size,
+            rows: 1,
+            cols: 1,
+            keymap,
+            theme,
+            renderer: Renderer::default(),
+            count: CountPrefix::default(),
+            cleared: 0,
+            exploded: None,
+        };
+        endless.resize(size);
+        endless
+    }
+
+    /// Fits the board to a terminal of `width` x `height`, centred on the
+    /// cursor.
+    pub fn resize(&mut self, size: (u16, u16)) {
+        self.size = size;
+        let (width, height) = size;
+        self.rows = (height as i64 - 2).max(1);
+        self.cols = ((width as i64 - 2) / TILE_WIDTH as i64).max(1);
+        self.top_left = (self.cursor.0 - self.rows / 2, self.cursor.1 - self.cols / 2);
+        self.follow();
+        self.renderer.invalidate();
+    }
+
+    pub fn redraw(&mut self) {
+        self.renderer.invalidate();
+    }
+
+    /// Scrolls so the cursor stays on screen and deals the chunks coming
+    /// into view, one chunk ahead.
+    fn follow(&mut self) {
+        let (row, col) = self.cursor;
+        let (top, left) = &mut self.top_left;
+        *top = (*top).clamp(row - self.rows + 1, row);
+        *left = (*left).clamp(col - self.cols + 1, col);
+
+        self.store.deal_around(
+            (*top - CHUNK_SIZE, *left - CHUNK_SIZE),
+            self.rows + 2 * CHUNK_SIZE,
+            self.cols + 2 * CHUNK_SIZE,
+        );
+    }
+
+    /// Plays `key`, returning whether to quit.
+    pub fn tick(&mut self, key: &Key) -> bool {
+        let action = self.keymap.action(key);
+        if self.count.push(key, action) {
+            return false;
+        }
+        let count = self.count.take();
+        if let (Some(motion), None) = (action.and_then(Motion::from_action), self.exploded) {
+            self.cursor = self.motion(motion, count);
+        }
+
+        match (action, self.exploded) {
+            (Some(Action::Quit), _) => return true,
+            // The next board follows from this one's seed, so a whole
+            // session replays from the first seed.
+            (Some(Action::Restart), _) => {
+                *self = Self::new(
+                    mix(self.store.seed),
+                    self.store.density,
+                    self.keymap.clone(),
+                    self.theme.clone(),
+                    self.size,
+                );
+            }
+            (Some(Action::Flag), None) => self.store.cycle_mark(self.cursor),
+            (Some(Action::Reveal), None) => {
+                let revealed = match self.store.is_revealed(self.cursor) {
+                    true => self.store.chord(self.cursor),
+                    false => self.store.reveal(self.cursor),
+                };
+                match revealed {
+                    Some(revealed) => self.cleared += revealed,
+                    None => self.exploded = Some(self.lost_on()),
+                }
+            }
+            _ => {}
+        }
+
+        self.follow();
+        false
+    }
+
+    /// Where `motion` takes the cursor. Steps go on forever, but the board
+    /// has no edges, so jumps stay on screen: the row start and end, top
+    /// and bottom are those of the screen, and searches wrap around it.
+    fn motion(&mut self, motion: Motion, count: Option<usize>) -> Point {
+        let (row, col) = self.cursor;
+        let (top, left, cols) = (self.top_left.0, self.top_left.1, self.cols);
+        let (bottom, right) = (top + self.rows - 1, left + cols - 1);
+        let times = count.unwrap_or(1) as i64;
+        let on_screen = move |index: usize| (top + index as i64 / cols, left + index as i64 % cols);
+        let row_on_screen = |count: usize| (top + count as i64 - 1).min(bottom);
+
+        match motion {
+            Motion::Step(CursorDirection::Up) => (row - times, col),
+            Motion::Step(CursorDirection::Down) => (row + times, col),
+            Motion::Step(CursorDirection::Left) => (row, col - times),
+            Motion::Step(CursorDirection::Right) => (row, col + times),
+            Motion::Step(_) => self.cursor,
+            Motion::Jump(CursorJump::RowStart) => (row, left),
+            Motion::Jump(CursorJump::RowEnd) => (row, right),
+            Motion::Jump(CursorJump::Top) => (count.map_or(top, row_on_screen), col),
+            Motion::Jump(CursorJump::Bottom) => (count.map_or(bottom, row_on_screen), col),
+            Motion::Jump(jump) => {
+                let tiles = (self.rows * cols) as usize;
+                let start = ((row - top) * cols + col - left) as usize;
+                let store = &mut self.store;
+                let found = (0..times).fold(start, |from, _| {
+                    find_after(from, tiles, |index| {
+                        let point = on_screen(index);
+                        match jump {
+                            CursorJump::NextNumber => {
+                                store.is_revealed(point)
+                                    && !store.is_mine(point)
+                                    && neighbours(point).any(|p| store.is_hidden(p))
+                            }
+                            _ => store.is_hidden(point),
+                        }
+                    })
+                    .unwrap_or(from)
+                });
+                on_screen(found)
+            }
+        }
+    }
+
+    /// The mine a reveal around the cursor went off on.
+    fn lost_on(&mut self) -> Point {
+        std::iter::once(self.cursor)
+            .chain(neighbours(self.cursor))
+            .find(|&p| self.store.is_revealed(p) && self.store.is_mine(p))
+            .unwrap_or(self.cursor)
+    }
+
+    /// The tile at `point` as the board shows it. After a loss everything
+    /// on screen is uncovered, like `Field::game_over`.
+    fn tile(&mut self, point: Point) -> Tile {
+        Tile {
+            kind: self.store.kind(point),
+            revealed: self.store.is_revealed(point) || self.exploded.is_some(),
+            mark: self.store.mark(point),
+            exploded: self.exploded == Some(point),
+            selected: point == self.cursor,
+            ..Tile::new_empty(Position(0, 0, 0))
+        }
+    }
+
+    fn status_line(&self) -> Cell {
+        let hint = format!(
+            "{}: new board, {}: quit",
+            self.keymap.key_hint(Action::Restart),
+            self.keymap.key_hint(Action::Quit)
+        );
+        let status = match self.exploded {
+            None => format!(
+                "endless  {} cleared  seed {}  {}, {}",
+                self.cleared, self.store.seed, self.cursor.0, self.cursor.1
+            ),
+            Some(_) => format!("Boom! {} tiles cleared. {}", self.cleared, hint),
+        };
+
+        Cell::new(
+            status.chars().count() + 1,
+            format!("{} {}", self.theme.text(), status),
+        )
+    }
+
+    fn render(&mut self) -> Frame {
+        let (top, left) = self.top_left;
+        let mut frame: Frame = (top..top + self.rows)
+            .map(|row| {
+                let mut line = vec![Cell::new(1, " ".to_string())];
+                line.extend((left..left + self.cols).map(|col| {
+                    let tile = self.tile((row, col));
+                    Cell::new(TILE_WIDTH, Field::render_tile(&tile, &self.theme))
+                }));
+                line
+            })
+            .collect();
+        frame.push(vec![self.status_line()]);
+        frame
+    }
+
+    pub fn display<W: Write>(&mut self, stdout: &mut W) -> io::Result<()> {
+        let frame = self.render();
+
+        match self.theme.is_plain() {
+            true => Renderer::draw_plain(stdout, frame),
+            false => self.renderer.draw(stdout, frame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mines(store: &mut ChunkStore, key: Point) -> Vec<bool> {
+        (0..CHUNK_SIZE)
+            .flat_map(|row| (0..CHUNK_SIZE).map(move |col| (row, col)))
+            .map(|(row, col)| store.is_mine((key.0 * CHUNK_SIZE + row, key.1 * CHUNK_SIZE + col)))
+            .collect()
+    }
+
+    #[test]
+    fn test_chunks_are_deterministic() {
+        let mut first = ChunkStore::new(7, DEFAULT_DENSITY);
+        let mut second = ChunkStore::new(7, DEFAULT_DENSITY);
+
+        // Dealt in a different order, the chunks come out the same.
+        let a = mines(&mut first, (3, -2));
+        let b = mines(&mut first, (-1, 0));
+        assert_eq!(mines(&mut second, (-1, 0)), b);
+        assert_eq!(mines(&mut second, (3, -2)), a);
+        assert_ne!(a, b);
+        assert_ne!(mines(&mut ChunkStore::new(8, DEFAULT_DENSITY), (3, -2)), a);
+
+        // Numbers on a chunk's edge count the mines next door.
+        let mut store = ChunkStore::new(1, 1.0);
+        assert_eq!(store.number((-3, 0)), 8);
+        assert!(!store.is_mine((1, 1)) && store.is_mine((2, 2)));
+    }
+
+    #[test]
+    fn test_endless_deals_chunks_ahead() {
+        let mut endless = Endless::new(
+            7,
+            DEFAULT_DENSITY,
+            Keymap::default(),
+            Theme::ascii(),
+            (20, 8),
+        );
+        let dealt = endless.store.chunks.len();
+        assert!(!endless.tick(&Key::Char(' ')));
+        assert!(endless.cleared > 0);
+
+        for _ in 0..CHUNK_SIZE {
+            endless.tick(&Key::Char('d'));
+        }
+        assert!(endless.store.chunks.len() > dealt);
+        assert_eq!(endless.cursor, (0, CHUNK_SIZE));
+    }
+
+    #[test]
+    fn test_endless_motions_and_restart() {
+        let mut endless = Endless::new(
+            7,
+            DEFAULT_DENSITY,
+            Keymap::default(),
+            Theme::ascii(),
+            (20, 8),
+        );
+
+        for key in ['1', '2', 'd', '3', 's'] {
+            endless.tick(&Key::Char(key));
+        }
+        assert_eq!(endless.cursor, (3, 12));
+        endless.tick(&Key::Char('^'));
+        assert_eq!(endless.cursor.1, endless.top_left.1);
+
+        // A new board follows from the seed, not from chance.
+        endless.tick(&Key::Char('r'));
+        assert_eq!(endless.store.seed, mix(7));
+        assert_eq!(endless.cursor, (0, 0));
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::io;
+
+use crate::keymap::KeymapError;
+use crate::theme::ThemeError;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidDimensions { rows: usize, cols: usize },
+    InvalidBombPercentile(f32),
+    InvalidBombCount { bombs: usize, max: usize },
+    UnknownDifficulty(String),
+    MissingCustomParams,
+    Keymap(KeymapError),
+    Theme(ThemeError),
+    Stats(String),
+    Network(String),
+    Layout(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The process exit code: 2 for invalid input, 1 for everything else.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) | Error::Stats(_) | Error::Network(_) => 1,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidDimensions { rows, cols } => write!(
+                f,
+                "a {}x{} board is too small, rows and cols must be at least 1",
+                rows, cols
+            ),
+            Error::InvalidBombPercentile(bomb_percentile) => write!(
+                f,
+                "bomb percentile must be between 0 and 1 (exclusive), got {}",
+                bomb_percentile
+            ),
+            Error::InvalidBombCount { max: 0, .. } => write!(
+                f,
+                "this board is too small to fit any bombs outside the safe area around the first click"
+            ),
+            Error::InvalidBombCount { bombs, max } => write!(
+                f,
+                "cannot place {} bombs, this board fits between 1 and {} outside the safe area around the first click",
+                bombs, max
+            ),
+            Error::UnknownDifficulty(difficulty) => write!(
+                f,
+                "unknown difficulty `{}`, expected 0-3, easy, medium, hard or nightmare",
+                difficulty
+            ),
+            Error::MissingCustomParams => write!(
+                f,
+                "pick a --difficulty, or pass --rows, --cols and --mines or --bomb-percentile for a custom board"
+            ),
+            Error::Keymap(e) => write!(f, "{}", e),
+            Error::Theme(e) => write!(f, "{}", e),
+            Error::Stats(e) => write!(f, "stats: {}", e),
+            Error::Network(e) => write!(f, "network: {}", e),
+            Error::Layout(e) => write!(f, "{}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<KeymapError> for Error {
+    fn from(e: KeymapError) -> Self {
+        Error::Keymap(e)
+    }
+}
+
+impl From<ThemeError> for Error {
+    fn from(e: ThemeError) -> Self {
+        Error::Theme(e)
+    }
+}
@@ -1,117 +1,133 @@
 use crate::{
+    error::{Error, Result},
+    renderer::{Cell, Frame},
+    rules::RuleSet,
     sweeper::{Position, UnsafePosition},
-    tile::{Tile, TileKind},
+    theme::Theme,
+    tile::{Clue, Mark, Tile, TileKind},
+    viewport::{Viewport, LAYER_GAP, TILE_WIDTH},
 };
-use rand::thread_rng;
+use rand::{thread_rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use std::fmt;
-use termion::color;
 
 use rand::Rng;
 
+/// The tiles row by row. A layered field stacks its layers, the rows of
+/// layer 1 follow the rows of layer 0 and so on.
 pub type TileMatrix = Vec<Vec<Tile>>;
 
 trait TileMatrixTrait {
-    fn create_empty(rows: usize, cols: usize) -> Self;
-    fn check_bounds(&self, position: UnsafePosition) -> Option<TileMatrix>;
+    fn create_empty(layers: usize, rows: usize, cols: usize) -> Self;
+    fn shape(&self) -> (usize, usize, usize);
+    fn check_bounds(&self, position: UnsafePosition) -> Option<&Self>;
     fn get_tile(&self, position: &UnsafePosition) -> Option<Tile>;
     fn get_neighbours(&self, position: UnsafePosition) -> Vec<Tile>;
     fn populate_bombs(
         &self,
+        rng: &mut ChaCha8Rng,
         selected_point: Position,
-        rows: usize,
-        cols: usize,
         bombs: usize,
+        max_mines: u8,
         prev_tile_matrix: Option<TileMatrix>,
     ) -> Self;
     fn populate_neighbours(&self, with_bombs: bool) -> Self;
 }
 
 impl TileMatrixTrait for TileMatrix {
-    fn create_empty(rows: usize, cols: usize) -> Self {
-        let row_range = 0..rows;
-        let col_range = 0..cols;
-
-        row_range
-            .map(|row| {
-                col_range
-                    .to_owned()
-                    .map(|col| Tile::new_empty(Position(row, col)))
-                    .collect()
+    fn create_empty(layers: usize, rows: usize, cols: usize) -> Self {
+        (0..layers)
+            .flat_map(|layer| {
+                (0..rows).map(move |row| {
+                    (0..cols)
+                        .map(|col| Tile::new_empty(Position(row, col, layer)))
+                        .collect()
+                })
             })
             .collect::<Self>()
     }
 
-    fn check_bounds(&self, position: UnsafePosition) -> Option<TileMatrix> {
-        let (x, y) = (position.0, position.1);
-
-        let lower_bound = 0;
-        let row_upper_bound = (self.len() - 1) as i32;
-        let col_upper_bound = (self[0].len() - 1) as i32;
+    /// Layers, rows per layer and columns, read off the last tile.
+    fn shape(&self) -> (usize, usize, usize) {
+        let Position(row, col, layer) = self[self.len() - 1][self[0].len() - 1].position;
+        (layer + 1, row + 1, col + 1)
+    }
 
-        let is_x_within_bounds = lower_bound <= x && x <= row_upper_bound;
-        let is_y_within_bounds = lower_bound <= y && y <= col_upper_bound;
+    fn check_bounds(&self, position: UnsafePosition) -> Option<&Self> {
+        let (layers, rows, cols) = self.shape();
+        let is_within = |value: i32, len: usize| 0 <= value && value < len as i32;
 
-        match is_x_within_bounds && is_y_within_bounds {
-            true => Some(self.clone()),
+        match is_within(position.0, rows)
+            && is_within(position.1, cols)
+            && is_within(position.2, layers)
+        {
+            true => Some(self),
             false => None,
         }
     }
 
     fn get_tile(&self, position: &UnsafePosition) -> Option<Tile> {
-        self.to_vec()
-            .check_bounds(position.clone())
-            .and_then(|matrix| Some(matrix[position.0 as usize][position.1 as usize].clone()))
+        let (_, rows, _) = self.shape();
+        self.check_bounds(position.clone()).map(|matrix| {
+            matrix[position.2 as usize * rows + position.0 as usize][position.1 as usize].clone()
+        })
     }
 
+    /// The up to 8 tiles around `position` on its layer, and the 9 above and
+    /// the 9 below it on a layered field.
     fn get_neighbours(&self, position: UnsafePosition) -> Vec<Tile> {
-        let row_range = position.0 - 1..=position.0 + 1;
-        let col_range = position.1 - 1..=position.1 + 1;
-
-        row_range
-            .flat_map(|row| {
-                col_range
-                    .clone()
-                    .flat_map(move |col| self.get_tile(&UnsafePosition(row, col)))
+        let layer_range = position.2 - 1..=position.2 + 1;
+        let row_range = &(position.0 - 1..=position.0 + 1);
+        let col_range = &(position.1 - 1..=position.1 + 1);
+
+        layer_range
+            .flat_map(|layer| {
+                row_range.clone().flat_map(move |row| {
+                    col_range
+                        .clone()
+                        .flat_map(move |col| self.get_tile(&UnsafePosition(row, col, layer)))
+                })
             })
-            .filter(|t| t.position != position.to_safe())
+            .filter(|t| t.position.to_unsafe() != position)
             .collect()
     }
 
     fn populate_bombs(
         &self,
+        rng: &mut ChaCha8Rng,
         selected_point: Position,
-        rows: usize,
-        cols: usize,
         bombs: usize,
+        max_mines: u8,
         prev_tile_matrix: Option<TileMatrix>,
     ) -> Self {
         let bomb_generation_frequency = 0.15; //15% frequency
         let mut bombs_populated = 0;
+        let (layers, rows, cols) = self.shape();
 
         let tile_matrix = prev_tile_matrix
-            .unwrap_or(Self::create_empty(rows, cols))
+            .unwrap_or(Self::create_empty(layers, rows, cols))
             .into_iter()
-            .enumerate()
-            .map(|(row, tiles)| {
+            .map(|tiles| {
                 tiles
                     .into_iter()
-                    .enumerate()
-                    .map(|(col, tile)| match bombs - bombs_populated {
+                    .map(|tile| match bombs - bombs_populated {
                         0 => tile,
                         _ => {
-                            let is_bomb =
-                                (thread_rng().gen_range(0.0..1.0)) <= bomb_generation_frequency;
+                            let is_bomb = tile.mines() < max_mines
+                                && (rng.gen_range(0.0..1.0)) <= bomb_generation_frequency;
 
-                            let is_selected_point = Position(row, col) == selected_point
-                                || tile.neighbours.iter().any(|t| t.position == selected_point);
+                            let Position(row, col, layer) = tile.position;
+                            let is_selected_point = row.abs_diff(selected_point.0) <= 1
+                                && col.abs_diff(selected_point.1) <= 1
+                                && layer.abs_diff(selected_point.2) <= 1;
 
                             match is_selected_point {
-                                true => Tile::new_empty(Position(row, col)),
+                                true => Tile::new_empty(tile.position),
                                 false => match is_bomb {
                                     false => tile,
                                     true => {
                                         bombs_populated += 1;
-                                        Tile::new_bomb(Position(row, col))
+                                        Tile::new_bomb(tile.position.clone(), tile.mines() + 1)
                                     }
                                 },
                             }
@@ -124,51 +140,40 @@ impl TileMatrixTrait for TileMatrix {
         match bombs - bombs_populated {
             0 => tile_matrix,
             _ => self.populate_bombs(
+                rng,
                 selected_point,
-                rows,
-                cols,
                 bombs - bombs_populated,
+                max_mines,
                 Some(tile_matrix),
             ),
         }
     }
 
     fn populate_neighbours(&self, with_bombs: bool) -> Self {
-        let replace_empty = |neighbours: Vec<Tile>, position: UnsafePosition| {
+        let replace_empty = |neighbours: Vec<Tile>, position: Position| {
             let filtered_neighbours: Vec<Tile> = neighbours
                 .into_iter()
                 .filter(|tile| tile.is_bomb())
                 .collect();
 
             match filtered_neighbours.len() {
-                0 => Tile::new_empty(position.to_safe()),
-                _ => Tile::new_safe(
-                    position.to_safe(),
-                    filtered_neighbours.into_iter().collect(),
-                ),
+                0 => Tile::new_empty(position),
+                _ => Tile::new_safe(position, filtered_neighbours.into_iter().collect()),
             }
         };
 
         self.iter()
-            .enumerate()
-            .map(|(row, tiles)| {
-                let mapped_tiles = tiles.into_iter().enumerate().map(|(col, tile)| {
-                    (
-                        col,
-                        tile.set_neighbours(self.get_neighbours(Position(row, col).to_unsafe())),
-                    )
+            .map(|tiles| {
+                let mapped_tiles = tiles.iter().map(|tile| {
+                    tile.set_neighbours(self.get_neighbours(tile.position.to_unsafe()))
                 });
                 if !with_bombs {
-                    mapped_tiles.map(|(_, tile)| tile).collect()
+                    mapped_tiles.collect()
                 } else {
                     mapped_tiles
-                        .into_iter()
-                        .map(|(col, tile)| match tile.kind {
-                            TileKind::Empty => replace_empty(
-                                tile.neighbours,
-                                UnsafePosition(row as i32, col as i32),
-                            ),
-                            _ => tile.clone(),
+                        .map(|tile| match tile.kind {
+                            TileKind::Empty => replace_empty(tile.neighbours, tile.position),
+                            _ => tile,
                         })
                         .collect()
                 }
@@ -180,9 +185,15 @@ impl TileMatrixTrait for TileMatrix {
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Field {
+    /// Boards of `rows` x `cols` stacked on each other, more than one only
+    /// in layered mode.
+    pub layers: usize,
     pub rows: usize,
     pub cols: usize,
     bombs: usize,
+    /// Seeds bomb placement, the same seed and first click give the same board.
+    pub seed: u64,
+    pub rules: RuleSet,
     pub tile_matrix: TileMatrix,
 }
 
@@ -190,30 +201,358 @@ impl fmt::Display for Field {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for tiles in self.tile_matrix.iter() {
             for tile in tiles {
-                match tile.selected {
-                    true => write!(f, "{}[{}]", color::Fg(color::White), tile.repr()).unwrap(),
-                    false => write!(f, "{}", tile.padded_repr()).unwrap(),
-                };
+                write!(f, "{}", Self::render_tile(tile, &Theme::default()))?;
             }
-            writeln!(f, "").unwrap();
+            writeln!(f)?;
         }
-        writeln!(f, "")
+        writeln!(f)
     }
 }
 
 #[allow(dead_code)]
 impl Field {
-    pub fn create(rows: usize, cols: usize, bomb_percentile: f32) -> Self {
-        assert!(
-            bomb_percentile > 0.0 && bomb_percentile < 1.0,
-            "Bomb percentile must be between 0 and 1"
-        );
+    pub fn create(rows: usize, cols: usize, bomb_percentile: f32) -> Result<Self> {
+        if !(bomb_percentile > 0.0 && bomb_percentile < 1.0) {
+            return Err(Error::InvalidBombPercentile(bomb_percentile));
+        }
+
         let bombs = (((rows * cols) as f32) * bomb_percentile) as usize;
+        Self::create_with_bombs(rows, cols, bombs)
+    }
+
+    /// Fails unless the bombs fit outside the 3x3 area around the first
+    /// click, which `populate` always keeps free.
+    pub fn create_with_bombs(rows: usize, cols: usize, bombs: usize) -> Result<Self> {
+        Self::create_layered(1, rows, cols, bombs)
+    }
+
+    /// A stack of `layers` boards where a tile also touches the tiles above
+    /// and below it, with the 3x3x3 block around the first click kept free.
+    pub fn create_layered(layers: usize, rows: usize, cols: usize, bombs: usize) -> Result<Self> {
+        Self::validate_layered(layers, rows, cols, bombs)?;
+
+        Ok(Self {
+            layers,
+            rows,
+            cols,
+            bombs,
+            seed: thread_rng().gen(),
+            rules: RuleSet::Classic,
+            tile_matrix: TileMatrix::create_empty(layers, rows, cols),
+        })
+    }
+
+    /// Checks the arguments of `create_with_bombs` without allocating a field.
+    pub fn validate(rows: usize, cols: usize, bombs: usize) -> Result<()> {
+        Self::validate_layered(1, rows, cols, bombs)
+    }
+
+    /// Checks the arguments of `create_layered` without allocating a field.
+    pub fn validate_layered(layers: usize, rows: usize, cols: usize, bombs: usize) -> Result<()> {
+        if layers == 0 || rows == 0 || cols == 0 {
+            return Err(Error::InvalidDimensions { rows, cols });
+        }
+
+        let max = layers * rows * cols - layers.min(3) * rows.min(3) * cols.min(3);
+        if bombs == 0 || bombs > max {
+            return Err(Error::InvalidBombCount { bombs, max });
+        }
+
+        Ok(())
+    }
+
+    /// A populated field with bombs at exactly `bombs`.
+    pub fn from_bombs(rows: usize, cols: usize, bombs: &[Position]) -> Self {
+        let mines: Vec<(Position, u8)> = bombs.iter().map(|bomb| (bomb.clone(), 1)).collect();
+        Self::from_mines(rows, cols, &mines)
+    }
+
+    /// A populated field with the given number of mines on each tile of
+    /// `mines`, under the classic rules unless changed with `with_rules`.
+    pub fn from_mines(rows: usize, cols: usize, mines: &[(Position, u8)]) -> Self {
         Self {
+            layers: 1,
             rows,
             cols,
+            bombs: mines.iter().map(|(_, mines)| *mines as usize).sum(),
+            seed: 0,
+            rules: RuleSet::Classic,
+            tile_matrix: TileMatrix::create_empty(1, rows, cols)
+                .into_iter()
+                .map(|tiles| {
+                    tiles
+                        .into_iter()
+                        .map(|tile| {
+                            match mines
+                                .iter()
+                                .find(|(position, _)| *position == tile.position)
+                            {
+                                Some((position, mines)) => Tile::new_bomb(position.clone(), *mines),
+                                None => tile,
+                            }
+                        })
+                        .collect()
+                })
+                .collect::<TileMatrix>()
+                .populate_neighbours(true),
+        }
+    }
+
+    /// The field with a bomb added at `position`, or taken away once the
+    /// tile holds as many as the rules allow, and the numbers around it
+    /// updated. Safe tiles stay revealed, nothing stays flagged.
+    pub fn toggle_bomb(&self, position: &Position) -> Self {
+        let mines: Vec<(Position, u8)> = self
+            .tile_matrix
+            .iter()
+            .flatten()
+            .map(|tile| match tile.position == *position {
+                true => (
+                    tile.position.clone(),
+                    (tile.mines() + 1) % (self.rules.max_mines() + 1),
+                ),
+                false => (tile.position.clone(), tile.mines()),
+            })
+            .filter(|(_, mines)| *mines > 0)
+            .collect();
+        let field = Self::from_mines(self.rows, self.cols, &mines).with_rules(self.rules);
+
+        Self {
+            seed: self.seed,
+            tile_matrix: field
+                .tile_matrix
+                .iter()
+                .zip(&self.tile_matrix)
+                .map(|(tiles, previous)| {
+                    tiles
+                        .iter()
+                        .zip(previous)
+                        .map(
+                            |(tile, previous)| match previous.revealed && !tile.is_bomb() {
+                                true => tile.reveal(),
+                                false => tile.clone(),
+                            },
+                        )
+                        .collect()
+                })
+                .collect(),
+            ..field
+        }
+    }
+
+    /// A field of `tiles` as given, e.g. as seen by a co-op client.
+    pub fn from_tiles(tiles: TileMatrix, bombs: usize) -> Self {
+        Self {
+            layers: 1,
+            rows: tiles.len(),
+            cols: tiles.first().map_or(0, Vec::len),
             bombs,
-            tile_matrix: TileMatrix::create_empty(rows, cols),
+            seed: 0,
+            rules: RuleSet::Classic,
+            tile_matrix: tiles,
+        }
+    }
+
+    pub fn render_tile(tile: &Tile, theme: &Theme) -> String {
+        match (tile.selected, tile.is_wide(theme)) {
+            (true, false) => format!("{}[{}]", theme.text(), tile.repr(theme)),
+            (true, true) => format!("{}[{}", theme.text(), tile.repr(theme)),
+            (false, _) => tile.padded_repr(theme),
+        }
+    }
+
+    /// Renders the part of the field inside `viewport`, one line of cells per
+    /// terminal row, framed by indicators pointing at the hidden parts.
+    pub fn render(&self, viewport: &Viewport, theme: &Theme) -> Frame {
+        self.render_with(viewport, theme, &|tile| Self::render_tile(tile, theme))
+    }
+
+    /// Like `render`, drawing each tile with `render_tile`, which must return
+    /// `TILE_WIDTH` columns.
+    pub fn render_with(
+        &self,
+        viewport: &Viewport,
+        theme: &Theme,
+        render_tile: &dyn Fn(&Tile) -> String,
+    ) -> Frame {
+        let width = viewport.width() + 2;
+        let [up, down, left, right] = theme.glyphs.arrows();
+        let indicator = |arrow: char, hidden: usize, visible: bool| match visible {
+            true => vec![Cell::new(
+                width,
+                format!(
+                    "{}{:^width$}",
+                    theme.text(),
+                    format!("{} {} more", arrow, hidden),
+                    width = width
+                ),
+            )],
+            false => vec![],
+        };
+        let edge = |arrow: char, visible: bool| {
+            Cell::new(
+                1,
+                format!(
+                    "{}{}",
+                    theme.text(),
+                    match visible {
+                        true => arrow,
+                        false => ' ',
+                    }
+                ),
+            )
+        };
+
+        // Layers are drawn side by side, one gap apart.
+        let layers = |cells: &dyn Fn(usize) -> Vec<Cell>| {
+            let mut line = vec![edge(left, viewport.can_scroll_left())];
+            for layer in viewport.layer_range() {
+                if layer > viewport.first_layer {
+                    line.push(Cell::new(
+                        LAYER_GAP,
+                        format!("{}{}", theme.text(), " ".repeat(LAYER_GAP)),
+                    ));
+                }
+                line.extend(cells(layer));
+            }
+            line.push(edge(right, viewport.can_scroll_right()));
+            line
+        };
+
+        let mut lines = vec![indicator(up, viewport.top, viewport.can_scroll_up())];
+        if self.layers > 1 {
+            let layer_width = viewport.cols * TILE_WIDTH;
+            lines.push(layers(&|layer| {
+                let label = match layer == viewport.cursor_layer {
+                    true => format!("{} layer {} {}", right, layer + 1, left),
+                    false => format!("layer {}", layer + 1),
+                };
+                vec![Cell::new(
+                    layer_width,
+                    format!(
+                        "{}{:^width$.width$}",
+                        theme.text(),
+                        label,
+                        width = layer_width
+                    ),
+                )]
+            }));
+        }
+        lines.extend(viewport.row_range().map(|row| {
+            layers(&|layer| {
+                viewport
+                    .col_range()
+                    .map(|col| {
+                        Cell::new(
+                            TILE_WIDTH,
+                            render_tile(self.tile(&Position(row, col, layer))),
+                        )
+                    })
+                    .collect()
+            })
+        }));
+        lines.push(indicator(
+            down,
+            self.rows - viewport.top - viewport.rows,
+            viewport.can_scroll_down(),
+        ));
+
+        lines
+    }
+
+    /// A flat board under the classic rules, the only kind `solver` reads.
+    pub fn is_classic(&self) -> bool {
+        self.layers == 1 && self.rules.is_classic()
+    }
+
+    pub fn bombs(&self) -> usize {
+        self.bombs
+    }
+
+    /// The field as it was before the first move: nothing revealed or marked.
+    pub fn game_start(&self) -> Self {
+        Self {
+            tile_matrix: self
+                .tile_matrix
+                .iter()
+                .map(|tiles| {
+                    tiles
+                        .iter()
+                        .map(|tile| Tile {
+                            revealed: false,
+                            mark: Mark::None,
+                            exploded: false,
+                            ..tile.clone()
+                        })
+                        .collect()
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether every tile without a bomb has been revealed.
+    pub fn is_cleared(&self) -> bool {
+        self.tile_matrix
+            .iter()
+            .flatten()
+            .all(|tile| tile.revealed || tile.is_bomb())
+    }
+
+    /// Whether a bomb has been revealed.
+    pub fn is_exploded(&self) -> bool {
+        self.tile_matrix
+            .iter()
+            .flatten()
+            .any(|tile| tile.revealed && tile.is_bomb())
+    }
+
+    /// The first revealed bomb, if any.
+    pub fn exploded_position(&self) -> Option<Position> {
+        self.tile_matrix
+            .iter()
+            .flatten()
+            .find(|tile| tile.revealed && tile.is_bomb())
+            .map(|tile| tile.position.clone())
+    }
+
+    pub fn with_seed(&self, seed: u64) -> Self {
+        Self {
+            seed,
+            ..self.clone()
+        }
+    }
+
+    /// The field under `rules`, with the numbers dealt again to match.
+    pub fn with_rules(&self, rules: RuleSet) -> Self {
+        Self {
+            rules,
+            ..self.clone()
+        }
+        .deal_clues()
+    }
+
+    /// Deals what each number shows under the field's rules, the same way
+    /// for the same seed.
+    fn deal_clues(&self) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        Self {
+            tile_matrix: self
+                .tile_matrix
+                .iter()
+                .map(|tiles| {
+                    tiles
+                        .iter()
+                        .map(|tile| match tile.kind {
+                            TileKind::Safe(number) => {
+                                tile.with_clue(self.rules.clue(number, &mut rng))
+                            }
+                            _ => tile.with_clue(Clue::Number),
+                        })
+                        .collect()
+                })
+                .collect(),
+            ..self.clone()
         }
     }
 
@@ -223,15 +562,20 @@ impl Field {
                 .tile_matrix
                 .clone()
                 .populate_bombs(
+                    &mut ChaCha8Rng::seed_from_u64(self.seed),
                     starting_point.clone(),
-                    self.rows,
-                    self.cols,
                     self.bombs,
+                    self.rules.max_mines(),
                     None,
                 )
                 .populate_neighbours(true),
             ..self.clone()
         }
+        .deal_clues()
+    }
+
+    pub fn tile(&self, position: &Position) -> &Tile {
+        &self.tile_matrix[position.2 * self.rows + position.0][position.1]
     }
 
     pub fn get_tile(&self, position: UnsafePosition) -> Option<Tile> {
@@ -251,13 +595,11 @@ impl Field {
         self.tile_matrix
             .clone()
             .into_iter()
-            .enumerate()
-            .map(|(row, tiles)| {
+            .map(|tiles| {
                 tiles
                     .into_iter()
-                    .enumerate()
-                    .map(|(col, tile)| {
-                        if tile_position == Position(row, col) {
+                    .map(|tile| {
+                        if tile.position == tile_position {
                             return map_selected(tile);
                         }
                         if let Some(map_rest) = optional_map_rest {
@@ -281,18 +623,16 @@ impl Field {
         }
     }
 
-    pub fn toggle_flag(&self, tile_position: &Position) -> Self {
+    /// Moves a hidden tile on to its next mark: flag (counting up to the
+    /// most mines a tile can hold), question mark, none.
+    pub fn cycle_mark(&self, tile_position: &Position) -> Self {
+        let max_mines = self.rules.max_mines();
         Self {
             tile_matrix: self.apply_on_tile(
                 tile_position.clone(),
-                &move |tile| {
-                    if tile.revealed {
-                        return tile;
-                    }
-                    match tile.flagged {
-                        true => tile.unflag(),
-                        false => tile.flag(),
-                    }
+                &move |tile| match tile.revealed || tile.exploded {
+                    true => tile,
+                    false => tile.with_mark(tile.mark.next(max_mines)),
                 },
                 None,
             ),
@@ -300,12 +640,40 @@ impl Field {
         }
     }
 
+    /// Reveals the safe tile at `position` from the start, as `o` in a
+    /// layout, or covers it again. Bombs are left alone.
+    pub fn toggle_revealed(&self, position: &Position) -> Self {
+        Self {
+            tile_matrix: self.apply_on_tile(
+                position.clone(),
+                &move |tile| match tile.is_bomb() {
+                    true => tile,
+                    false => Tile {
+                        revealed: !tile.revealed,
+                        ..tile
+                    },
+                },
+                None,
+            ),
+            ..self.clone()
+        }
+    }
+
+    /// Covers the bomb stepped on at `position` again, flagged, so a casual
+    /// game can go on.
+    pub fn defuse(&self, position: &Position) -> Self {
+        Self {
+            tile_matrix: self.apply_on_tile(position.clone(), &move |tile| tile.defuse(), None),
+            ..self.clone()
+        }
+    }
+
     pub fn reveal(&self, tile_position: &Position) -> Self {
         Self {
             tile_matrix: self.apply_on_tile(
                 tile_position.clone(),
                 &move |tile| {
-                    if tile.revealed || tile.flagged {
+                    if tile.revealed || tile.is_flagged() {
                         return tile;
                     }
 
@@ -317,13 +685,23 @@ impl Field {
         }
     }
 
-    pub fn game_over(&self) -> Self {
+    /// Reveals everything after stepping on the bomb at `fatal`. Marks are
+    /// kept, so wrong flags can be told from right ones.
+    pub fn game_over(&self, fatal: &Position) -> Self {
         Self {
             tile_matrix: self
                 .tile_matrix
                 .clone()
                 .into_iter()
-                .map(|tiles| tiles.into_iter().map(|t| t.unflag().reveal()).collect())
+                .map(|tiles| {
+                    tiles
+                        .into_iter()
+                        .map(|t| match t.position == *fatal {
+                            true => t.explode(),
+                            false => t.reveal(),
+                        })
+                        .collect()
+                })
                 .collect(),
             ..self.clone()
         }
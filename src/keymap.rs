@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use termion::event::Key;
+
+use crate::config::{self, Ini};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Flag,
+    Reveal,
+    RowStart,
+    RowEnd,
+    Top,
+    Bottom,
+    NextUnrevealed,
+    NextNumber,
+    /// Only used in layered mode.
+    PreviousLayer,
+    NextLayer,
+    Quit,
+    Restart,
+    /// Only used in the board editor.
+    Play,
+    Save,
+    TogglePreRevealed,
+    /// Always `Ctrl+Z`, handled by the game loop rather than `Sweeper::tick`.
+    Suspend,
+}
+
+impl Action {
+    const ALL: [Action; 19] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::RowStart,
+        Action::RowEnd,
+        Action::Top,
+        Action::Bottom,
+        Action::NextUnrevealed,
+        Action::NextNumber,
+        Action::PreviousLayer,
+        Action::NextLayer,
+        Action::Flag,
+        Action::Reveal,
+        Action::Quit,
+        Action::Restart,
+        Action::Play,
+        Action::Save,
+        Action::TogglePreRevealed,
+    ];
+
+    /// The name used for this action in keymap files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::RowStart => "row_start",
+            Action::RowEnd => "row_end",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::NextUnrevealed => "next_unrevealed",
+            Action::NextNumber => "next_number",
+            Action::PreviousLayer => "previous_layer",
+            Action::NextLayer => "next_layer",
+            Action::Flag => "flag",
+            Action::Reveal => "reveal",
+            Action::Quit => "quit",
+            Action::Restart => "restart",
+            Action::Play => "play",
+            Action::Save => "save",
+            Action::TogglePreRevealed => "toggle_pre_revealed",
+            Action::Suspend => "suspend",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KeymapError {
+    Parse(String),
+    UnknownPreset(String),
+    UnknownAction(String),
+    UnknownKey {
+        action: String,
+        key: String,
+    },
+    Reserved {
+        action: Action,
+        key: String,
+    },
+    /// Digits are always count prefixes.
+    Digit {
+        action: Action,
+        key: String,
+    },
+    Conflict {
+        key: String,
+        first: Action,
+        second: Action,
+    },
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Parse(reason) => write!(f, "could not read keymap: {}", reason),
+            KeymapError::UnknownPreset(name) => write!(
+                f,
+                "`{}` is neither a keymap file nor a preset (default, vim)",
+                name
+            ),
+            KeymapError::UnknownAction(name) => write!(f, "unknown action `{}` in keymap", name),
+            KeymapError::UnknownKey { action, key } => {
+                write!(f, "unknown key `{}` bound to `{}`", key, action)
+            }
+            KeymapError::Reserved { action, key } => write!(
+                f,
+                "`{}` is reserved and cannot be bound to `{}`",
+                key,
+                action.name()
+            ),
+            KeymapError::Digit { action, key } => write!(
+                f,
+                "`{}` cannot be bound to `{}`, digits are count prefixes",
+                key,
+                action.name()
+            ),
+            KeymapError::Conflict { key, first, second } => write!(
+                f,
+                "`{}` is bound to both `{}` and `{}`",
+                key,
+                first.name(),
+                second.name()
+            ),
+        }
+    }
+}
+
+/// Maps keys to the actions they trigger. `Ctrl+C` always quits, `Ctrl+Z`
+/// always suspends and digits are always count prefixes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: HashMap<Action, Vec<Key>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_bindings(&[
+            (Action::MoveUp, &["w", "i", "up"]),
+            (Action::MoveDown, &["s", "k", "down"]),
+            (Action::MoveLeft, &["a", "j", "left"]),
+            (Action::MoveRight, &["d", "l", "right"]),
+            (Action::RowStart, &["^", "home"]),
+            (Action::RowEnd, &["$", "end"]),
+            (Action::Top, &["g"]),
+            (Action::Bottom, &["G"]),
+            (Action::NextUnrevealed, &["n"]),
+            (Action::NextNumber, &["m"]),
+            (Action::PreviousLayer, &["<"]),
+            (Action::NextLayer, &[">", "tab"]),
+            (Action::Flag, &["f"]),
+            (Action::Reveal, &["space", "e"]),
+            (Action::Quit, &["q"]),
+            (Action::Restart, &["r"]),
+            (Action::Play, &["enter"]),
+            (Action::Save, &["ctrl-s"]),
+            (Action::TogglePreRevealed, &["o"]),
+        ])
+    }
+}
+
+impl Keymap {
+    pub fn vim() -> Self {
+        Self::from_bindings(&[
+            (Action::MoveUp, &["k", "up"]),
+            (Action::MoveDown, &["j", "down"]),
+            (Action::MoveLeft, &["h", "left"]),
+            (Action::MoveRight, &["l", "right"]),
+            (Action::RowStart, &["^", "home"]),
+            (Action::RowEnd, &["$", "end"]),
+            (Action::Top, &["g"]),
+            (Action::Bottom, &["G"]),
+            (Action::NextUnrevealed, &["n"]),
+            (Action::NextNumber, &["m"]),
+            (Action::PreviousLayer, &["<"]),
+            (Action::NextLayer, &[">", "tab"]),
+            (Action::Flag, &["f"]),
+            (Action::Reveal, &["space", "e"]),
+            (Action::Quit, &["q"]),
+            (Action::Restart, &["r"]),
+            (Action::Play, &["enter"]),
+            (Action::Save, &["ctrl-s"]),
+            (Action::TogglePreRevealed, &["o"]),
+        ])
+    }
+
+    fn from_bindings(bindings: &[(Action, &[&str])]) -> Self {
+        Self {
+            bindings: bindings
+                .iter()
+                .map(|(action, keys)| (*action, keys.iter().flat_map(|k| parse_key(k)).collect()))
+                .collect(),
+        }
+    }
+
+    /// Loads the keymap selected by `--keymap`, which is either a preset name
+    /// or a path, falling back to `keymap.ini` in the config directory. A
+    /// file only ever overrides the default preset.
+    pub fn load(keymap_override: Option<&str>) -> Result<Self, KeymapError> {
+        match keymap_override {
+            Some("default") => Ok(Self::default()),
+            Some("vim") => Ok(Self::vim()),
+            Some(path) => match config::read_ini(Path::new(path)).map_err(KeymapError::Parse)? {
+                Some(ini) => Self::default().with_overrides(&ini),
+                None => Err(KeymapError::UnknownPreset(path.to_string())),
+            },
+            None => match config::config_dir() {
+                Some(dir) => {
+                    match config::read_ini(&dir.join("keymap.ini")).map_err(KeymapError::Parse)? {
+                        Some(ini) => Self::default().with_overrides(&ini),
+                        None => Ok(Self::default()),
+                    }
+                }
+                None => Ok(Self::default()),
+            },
+        }
+    }
+
+    /// Replaces the keys of every action listed in the `[keys]` section,
+    /// e.g. `move_up = w, up`, then validates the result.
+    pub fn with_overrides(&self, ini: &Ini) -> Result<Self, KeymapError> {
+        let mut bindings = self.bindings.clone();
+
+        for (name, value) in ini.get("keys").into_iter().flatten() {
+            let action =
+                Action::from_name(name).ok_or_else(|| KeymapError::UnknownAction(name.clone()))?;
+
+            let keys = value
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(|key| {
+                    parse_key(key).ok_or_else(|| KeymapError::UnknownKey {
+                        action: name.clone(),
+                        key: key.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<Key>, KeymapError>>()?;
+
+            bindings.insert(action, keys);
+        }
+
+        let keymap = Self { bindings };
+        keymap.validate()?;
+        Ok(keymap)
+    }
+
+    fn validate(&self) -> Result<(), KeymapError> {
+        let mut seen: HashMap<Key, Action> = HashMap::new();
+
+        for action in Action::ALL {
+            for key in self.keys(action) {
+                if matches!(key, Key::Ctrl('c') | Key::Ctrl('z')) {
+                    return Err(KeymapError::Reserved {
+                        action,
+                        key: key_name(key),
+                    });
+                }
+                if let Key::Char('0'..='9') = key {
+                    return Err(KeymapError::Digit {
+                        action,
+                        key: key_name(key),
+                    });
+                }
+
+                if let Some(first) = seen.insert(*key, action) {
+                    if first != action {
+                        return Err(KeymapError::Conflict {
+                            key: key_name(key),
+                            first,
+                            second: action,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn keys(&self, action: Action) -> &[Key] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The name of the first key bound to `action`, for hints on screen.
+    pub fn key_hint(&self, action: Action) -> String {
+        self.keys(action).first().map_or(String::new(), key_name)
+    }
+
+    pub fn action(&self, key: &Key) -> Option<Action> {
+        match key {
+            Key::Ctrl('c') => Some(Action::Quit),
+            Key::Ctrl('z') => Some(Action::Suspend),
+            _ => Action::ALL
+                .into_iter()
+                .find(|action| self.keys(*action).contains(key)),
+        }
+    }
+}
+
+/// Parses a key name as written in keymap files: a single character, an
+/// arrow (`up`, `down`, `left`, `right`), a named key or `ctrl-<char>`.
+fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Char(c));
+    }
+
+    match name.to_lowercase().as_str() {
+        "space" => Some(Key::Char(' ')),
+        "enter" => Some(Key::Char('\n')),
+        "tab" => Some(Key::Char('\t')),
+        "esc" => Some(Key::Esc),
+        "backspace" => Some(Key::Backspace),
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        lower => lower
+            .strip_prefix("ctrl-")
+            .and_then(parse_key)
+            .and_then(|key| match key {
+                Key::Char(c) => Some(Key::Ctrl(c)),
+                _ => None,
+            }),
+    }
+}
+
+fn key_name(key: &Key) -> String {
+    match key {
+        Key::Char(' ') => "space".to_string(),
+        Key::Char('\n') => "enter".to_string(),
+        Key::Char('\t') => "tab".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Esc => "esc".to_string(),
+        Key::Backspace => "backspace".to_string(),
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        Key::Home => "home".to_string(),
+        Key::End => "end".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::parse_ini;
+
+    use super::*;
+
+    #[test]
+    fn test_keymap_overrides() {
+        let ini = parse_ini("[keys]\nmove_left = h, left\nmove_down = ctrl-n").unwrap();
+        let keymap = Keymap::default().with_overrides(&ini).unwrap();
+
+        assert_eq!(keymap.action(&Key::Char('h')), Some(Action::MoveLeft));
+        assert_eq!(keymap.action(&Key::Ctrl('n')), Some(Action::MoveDown));
+        assert_eq!(keymap.action(&Key::Char('a')), None);
+        assert_eq!(keymap.action(&Key::Ctrl('c')), Some(Action::Quit));
+
+        let ini = parse_ini("[keys]\nflag = e").unwrap();
+        assert_eq!(
+            Keymap::default().with_overrides(&ini),
+            Err(KeymapError::Conflict {
+                key: "e".to_string(),
+                first: Action::Flag,
+                second: Action::Reveal,
+            })
+        );
+
+        let ini = parse_ini("[keys]\nflag = f, 5").unwrap();
+        assert_eq!(
+            Keymap::default().with_overrides(&ini),
+            Err(KeymapError::Digit {
+                action: Action::Flag,
+                key: "5".to_string(),
+            })
+        );
+    }
+}
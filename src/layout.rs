@@ -0,0 +1,159 @@
+//! Board layouts: a field with its bombs at fixed places, one line per row
+//! with `*` for a bomb, `.` for a safe tile and `o` for a safe tile that is
+//! already revealed, as in puzzles. Blank lines and lines starting with `#`
+//! are ignored.
+//!
+//! ```text
+//! # a 3x4 board with two bombs and the bottom left corner open
+//! *...
+//! oo..
+//! oo*.
+//! ```
+//!
+//! Boards of other rule sets start with a `rules:` line, see `RuleSet::parse`.
+//! A digit from 2 to 9 stands for a tile with that many mines.
+//!
+//! ```text
+//! rules: multi-mine:3
+//! *..3
+//! oo..
+//! oo2.
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use crate::field::Field;
+use crate::rules::{RuleSet, MAX_MINES};
+use crate::sweeper::Position;
+
+pub fn parse(contents: &str) -> Result<Field, String> {
+    let mut lines: Vec<&str> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    let rules = match lines.first().and_then(|line| line.strip_prefix("rules:")) {
+        Some(rules) => {
+            let rules = RuleSet::parse(rules.trim())?;
+            lines.remove(0);
+            rules
+        }
+        None => RuleSet::Classic,
+    };
+    let rows = lines;
+    let cols = rows.first().map_or(0, |row| row.chars().count());
+    if cols == 0 {
+        return Err("the layout has no rows".to_string());
+    }
+
+    let mut mines = vec![];
+    let mut revealed = vec![];
+    for (row, line) in rows.iter().enumerate() {
+        if line.chars().count() != cols {
+            return Err(format!(
+                "row {} has {} tiles, expected {}",
+                row + 1,
+                line.chars().count(),
+                cols
+            ));
+        }
+        for (col, tile) in line.chars().enumerate() {
+            match tile {
+                '*' => mines.push((Position(row, col, 0), 1)),
+                '2'..='9' => {
+                    let count = tile.to_digit(10).unwrap() as u8;
+                    if count > rules.max_mines() {
+                        return Err(format!(
+                            "{} mines on a tile in row {}, the {} rules allow {}",
+                            count,
+                            row + 1,
+                            rules,
+                            rules.max_mines()
+                        ));
+                    }
+                    mines.push((Position(row, col, 0), count));
+                }
+                'o' => revealed.push(Position(row, col, 0)),
+                '.' => {}
+                _ => return Err(format!("unexpected `{}` in row {}", tile, row + 1)),
+            }
+        }
+    }
+
+    Ok(revealed.iter().fold(
+        Field::from_mines(rows.len(), cols, &mines).with_rules(rules),
+        |field, position| field.reveal(position),
+    ))
+}
+
+pub fn format(field: &Field) -> String {
+    let rules = match field.rules {
+        RuleSet::Classic => String::new(),
+        rules => format!("rules: {}\n", rules),
+    };
+    let rows: String = field
+        .tile_matrix
+        .iter()
+        .map(|tiles| {
+            let mut line: String = tiles
+                .iter()
+                .map(|tile| match (tile.mines(), tile.revealed) {
+                    (1, _) => '*',
+                    (mines @ 2..=MAX_MINES, _) => char::from(b'0' + mines),
+                    (_, true) => 'o',
+                    (_, false) => '.',
+                })
+                .collect();
+            line.push('\n');
+            line
+        })
+        .collect();
+
+    rules + &rows
+}
+
+/// Reads the layout at `path`, returning `None` if it does not exist.
+pub fn load(path: &Path) -> Result<Option<Field>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse(&contents)
+            .map(Some)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+pub fn save(path: &Path, field: &Field) -> Result<(), String> {
+    fs::write(path, format(field)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tile::TileKind;
+
+    use super::*;
+
+    #[test]
+    fn test_layout_round_trip() {
+        let field = parse("# two bombs\n*...\n\noo..\noo*.\n").unwrap();
+        assert_eq!((field.rows, field.cols, field.bombs()), (3, 4, 2));
+        assert_eq!(field.tile_matrix[1][1].kind, TileKind::Safe(2));
+        assert!(field.tile_matrix[1][1].revealed && !field.tile_matrix[0][1].revealed);
+        assert_eq!(format(&field), "*...\noo..\noo*.\n");
+
+        assert!(parse("*..\n..\n").is_err());
+        assert!(parse("*x.\n").is_err());
+        assert!(parse("# nothing\n").is_err());
+
+        let field = parse("rules: multi-mine:3\n*.3\noo.\n").unwrap();
+        assert_eq!(field.rules, RuleSet::MultiMine(3));
+        assert_eq!(
+            (field.bombs(), field.tile_matrix[1][1].kind),
+            (4, TileKind::Safe(4))
+        );
+        assert_eq!(format(&field), "rules: multi-mine:3\n*.3\noo.\n");
+        assert!(parse("*.3\n").is_err());
+        assert!(parse("rules: multi-mine:2\n*.3\n").is_err());
+    }
+}
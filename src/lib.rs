@@ -1,3 +1,23 @@
-mod field;
-mod sweeper;
-mod tile;
\ No newline at end of file
+pub mod config;
+pub mod coop;
+pub mod daily;
+pub mod editor;
+pub mod endless;
+pub mod error;
+pub mod field;
+pub mod keymap;
+pub mod layout;
+pub mod menu;
+pub mod net;
+pub mod puzzle;
+pub mod renderer;
+pub mod rules;
+pub mod solver;
+pub mod stats;
+pub mod sweeper;
+pub mod terminal;
+pub mod theme;
+pub mod tile;
+pub mod tutorial;
+pub mod viewport;
+pub mod watch;
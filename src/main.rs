@@ -1,76 +1,1194 @@
+mod config;
+mod coop;
+mod daily;
+mod editor;
+mod endless;
+mod error;
 mod field;
+mod keymap;
+mod layout;
+mod menu;
+mod net;
+mod puzzle;
+mod renderer;
+mod rules;
+mod solver;
+mod stats;
 mod sweeper;
+mod terminal;
+mod theme;
 mod tile;
+mod tutorial;
+mod viewport;
+mod watch;
 
-use sweeper::{Difficulty, Position, Sweeper};
+use coop::{Client, ClientMessage, Server};
+use daily::Date;
+use editor::{Editor, EditorChoice};
+use endless::Endless;
+use error::{Error, Result};
+use field::Field;
+use keymap::{Action, Keymap};
+use menu::{Menu, MenuChoice};
+use net::{Connection, Message};
+use puzzle::{Grade, Puzzle};
+use rules::RuleSet;
+use stats::Stats;
+use sweeper::{
+    Bombs, CustomParams, Difficulty, GameSettings, GameState, Opponent, Position, Sweeper,
+    TimeLimit,
+};
+use terminal::{Input, RestoreGuard, Signal};
+use theme::{Theme, ThemeError};
+use tutorial::Tutorial;
+use watch::Publisher;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use std::io::stdout;
+use std::io::{self, stdout, Stdout};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
 
-use termion;
-use termion::input::TermRead;
-use termion::raw::IntoRawMode;
+use termion::event::Key;
+use termion::input::{Keys, TermRead};
+use termion::raw::RawTerminal;
 
 use std::io::Write;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    #[clap(short, long, value_parser)]
-    difficulty: Option<u8>,
+    /// 0-3 or easy, medium, hard, nightmare
+    #[clap(
+        short,
+        long,
+        value_parser = Difficulty::parse,
+        conflicts_with_all = &["rows", "cols", "mines", "bomb-percentile"]
+    )]
+    difficulty: Option<Difficulty>,
 
+    /// Rows of a custom board, defaults to --cols or 16
     #[clap(short, long, value_parser)]
     rows: Option<usize>,
 
+    /// Columns of a custom board, defaults to --rows or 16
     #[clap(short, long, value_parser)]
     cols: Option<usize>,
 
+    /// Number of bombs on a custom board
+    #[clap(short, long, value_parser, conflicts_with = "bomb-percentile")]
+    mines: Option<usize>,
+
+    /// Share of tiles holding a bomb on a custom board, defaults to 0.15625
     #[clap(short, long, value_parser)]
     bomb_percentile: Option<f32>,
+
+    /// Keymap preset (default, vim) or path to a keymap file
+    #[clap(short, long, value_parser)]
+    keymap: Option<String>,
+
+    /// Colour theme: classic, high-contrast, deuteranopia-safe or monochrome
+    #[clap(short, long, value_parser)]
+    theme: Option<String>,
+
+    /// Draw with 7-bit characters only and no escape codes
+    #[clap(long, value_parser)]
+    ascii: bool,
+
+    /// Seed for bomb placement, the same seed and first click give the same board
+    #[clap(long, value_parser)]
+    seed: Option<u64>,
+
+    /// Only deal boards that can be cleared without guessing
+    #[clap(long, value_parser)]
+    no_guess: bool,
+
+    /// Casual game: only the Nth mine stepped on ends it, not counted in the stats
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        value_name = "N",
+        conflicts_with_all = &["daily", "host", "join", "serve", "coop"]
+    )]
+    lives: Option<u32>,
+
+    /// Lose when SECS seconds have passed since the first reveal, not counted in the stats
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        value_name = "SECS",
+        conflicts_with_all = &["daily", "host", "join", "serve", "coop"]
+    )]
+    countdown: Option<u64>,
+
+    /// Time attack: clear as many boards as possible in MINUTES minutes
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        value_name = "MINUTES",
+        conflicts_with_all = &["countdown", "daily", "host", "join", "serve", "coop"]
+    )]
+    attack: Option<u64>,
+
+    /// Rule set: classic, multi-mine[:K] for up to K mines per tile (3 by default), liar or hidden-numbers
+    #[clap(
+        long,
+        value_parser = RuleSet::parse,
+        value_name = "RULES",
+        default_value_t,
+        conflicts_with_all = &["no-guess", "daily", "host", "join", "serve", "coop", "publish"]
+    )]
+    rules: RuleSet,
+
+    /// Play today's board, the same for everyone on the same day
+    #[clap(
+        long,
+        value_parser,
+        conflicts_with_all = &["difficulty", "rows", "cols", "mines", "bomb-percentile", "seed", "no-guess"]
+    )]
+    daily: bool,
+
+    /// Wait for another player to race on this board, on 0.0.0.0:7878 or ADDR
+    #[clap(
+        long,
+        value_parser,
+        value_name = "ADDR",
+        min_values = 0,
+        default_missing_value = "0.0.0.0:7878",
+        conflicts_with_all = &["daily", "join"]
+    )]
+    host: Option<String>,
+
+    /// Race another player on their board, port 7878 unless ADDR has one
+    #[clap(
+        long,
+        value_parser,
+        value_name = "ADDR",
+        conflicts_with_all = &["difficulty", "rows", "cols", "mines", "bomb-percentile", "seed", "no-guess", "daily"]
+    )]
+    join: Option<String>,
+
+    /// Host a co-op game for any number of players on 0.0.0.0:7878 or ADDR
+    #[clap(
+        long,
+        value_parser,
+        value_name = "ADDR",
+        min_values = 0,
+        default_missing_value = "0.0.0.0:7878",
+        conflicts_with_all = &["daily", "host", "join"]
+    )]
+    serve: Option<String>,
+
+    /// Play on a co-op server, port 7878 unless ADDR has one
+    #[clap(
+        long,
+        value_parser,
+        value_name = "ADDR",
+        conflicts_with_all = &["difficulty", "rows", "cols", "mines", "bomb-percentile", "seed", "no-guess", "daily", "host", "join", "serve"]
+    )]
+    coop: Option<String>,
+
+    /// Let other terminals `watch` this game, on 127.0.0.1:7878 or ADDR, which
+    /// may also be the path of a Unix socket
+    #[clap(
+        long,
+        value_parser,
+        value_name = "ADDR",
+        min_values = 0,
+        default_missing_value = "127.0.0.1:7878",
+        conflicts_with_all = &["serve", "coop"]
+    )]
+    publish: Option<String>,
+
+    /// Play a board without edges, scored by the tiles cleared before the first mine
+    #[clap(
+        long,
+        value_parser,
+        conflicts_with_all = &["difficulty", "rows", "cols", "mines", "no-guess", "daily", "lives", "countdown", "attack", "rules", "host", "join", "serve", "coop", "publish"]
+    )]
+    endless: bool,
+
+    /// Play a stack of L boards where tiles touch the layers above and below,
+    /// each layer 8x8 unless --rows and --cols say otherwise
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u64).range(2..=9),
+        value_name = "L",
+        conflicts_with_all = &["difficulty", "no-guess", "daily", "lives", "countdown", "attack", "rules", "endless", "host", "join", "serve", "coop", "publish"]
+    )]
+    layers: Option<u64>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Watch a game started with --publish, read-only
+    Watch {
+        /// Port 7878 unless ADDR has one, or the path of a Unix socket
+        #[clap(value_parser)]
+        addr: String,
+    },
+    /// Place bombs by hand and save the board as a layout file
+    Edit {
+        /// Layout file to edit, created on the first save if missing
+        #[clap(value_parser)]
+        file: PathBuf,
+
+        /// Rows of a new board
+        #[clap(short, long, value_parser, default_value_t = 16)]
+        rows: usize,
+
+        /// Columns of a new board
+        #[clap(short, long, value_parser, default_value_t = 16)]
+        cols: usize,
+    },
+    /// Learn the rules and controls step by step
+    Tutorial,
+    /// Solve a bundled puzzle or a layout file, or list the bundled puzzles
+    Puzzle {
+        /// Name of a bundled puzzle or path of a layout file
+        #[clap(value_parser)]
+        puzzle: Option<String>,
+
+        /// Print a new puzzle of this grade (easy, medium, hard) as a layout
+        #[clap(long, value_parser = Grade::parse, conflicts_with = "puzzle")]
+        generate: Option<Grade>,
+
+        /// Rows of a generated puzzle
+        #[clap(short, long, value_parser, default_value_t = 9)]
+        rows: usize,
+
+        /// Columns of a generated puzzle
+        #[clap(short, long, value_parser, default_value_t = 9)]
+        cols: usize,
+
+        /// Mines in a generated puzzle
+        #[clap(short, long, value_parser, default_value_t = 10)]
+        mines: usize,
+
+        /// Seed of the first board tried when generating
+        #[clap(long, value_parser)]
+        seed: Option<u64>,
+    },
+}
+
+impl Args {
+    fn custom_params(&self) -> Option<CustomParams> {
+        let bombs = match (self.mines, self.bomb_percentile) {
+            (Some(mines), _) => Some(Bombs::Count(mines)),
+            (None, Some(bomb_percentile)) => Some(Bombs::Percentile(bomb_percentile)),
+            (None, None) => None,
+        };
+
+        match (self.layers, self.rows, self.cols, bombs) {
+            (Some(layers), rows, cols, bombs) => {
+                Some(CustomParams::layered(layers as usize, rows, cols, bombs))
+            }
+            (None, None, None, None) => None,
+            (None, rows, cols, bombs) => Some(CustomParams::with_defaults(rows, cols, bombs)),
+        }
+    }
+
+    /// `None` when no board was picked, the menu asks for one then, or when
+    /// the board flags are meant for `--endless`.
+    fn settings(&self) -> Option<GameSettings> {
+        if self.endless {
+            return None;
+        }
+        if self.daily {
+            return Some(GameSettings::daily(Date::today()));
+        }
+
+        let custom_params = self.custom_params();
+
+        match (self.difficulty, custom_params) {
+            (None, None) => None,
+            (difficulty, custom_params) => Some(GameSettings {
+                difficulty: difficulty.unwrap_or(Difficulty::Custom),
+                custom_params,
+                seed: self.seed,
+                no_guess: self.no_guess,
+                daily: None,
+                lives: self.lives,
+                time_limit: self.time_limit(),
+                rules: self.rules,
+            }),
+        }
+    }
+
+    fn time_limit(&self) -> Option<TimeLimit> {
+        match (self.countdown, self.attack) {
+            (Some(secs), _) => Some(TimeLimit::Countdown(Duration::from_secs(secs))),
+            (None, Some(minutes)) => Some(TimeLimit::Attack(Duration::from_secs(minutes * 60))),
+            (None, None) => None,
+        }
+    }
+
+    fn theme(&self) -> Result<Theme> {
+        match self.ascii {
+            true => Ok(Theme::ascii()),
+            false => {
+                let settings = config::settings().map_err(ThemeError::Parse)?;
+                Ok(Theme::load(self.theme.as_deref(), &settings)?)
+            }
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
-    let mut stdout = stdout().into_raw_mode().unwrap();
-    let mut stdin = termion::async_stdin().keys();
-    let difficulty = match args.difficulty {
-        Some(0) => Difficulty::Easy,
-        Some(1) => Difficulty::Medium,
-        Some(2) => Difficulty::Hard,
-        Some(3) => Difficulty::Nightmare,
-        _ => Difficulty::Custom,
+    if let Err(e) = run(args) {
+        eprintln!("error: {}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+/// What the menu and game loops react to.
+enum Event {
+    Key(Key),
+    Resize((u16, u16)),
+    /// Back from a suspend, everything must be redrawn.
+    Resume,
+    Terminate,
+}
+
+/// The other end of a race and the tile both players start from.
+struct Race {
+    connection: Connection,
+    opening: Position,
+}
+
+impl Race {
+    /// Deals the board to race on to whoever connects to `addr`.
+    fn host(addr: &str, settings: Option<GameSettings>) -> Result<(Self, GameSettings)> {
+        let settings = settings.unwrap_or_default();
+        let settings = GameSettings {
+            seed: Some(settings.seed.unwrap_or_else(rand::random)),
+            ..settings
+        };
+        let field = Sweeper::from_settings(&settings)?.field;
+        let opening = Position(field.rows / 2, field.cols / 2, 0);
+
+        let listener = net::listen(addr)?;
+        eprintln!("waiting for an opponent on {}", listener.local_addr()?);
+        let connection = net::accept(&listener, &settings, &opening)?;
+
+        Ok((
+            Self {
+                connection,
+                opening,
+            },
+            settings,
+        ))
+    }
+
+    fn join(addr: &str) -> Result<(Self, GameSettings)> {
+        eprintln!("connecting to {}", addr);
+        let (connection, settings, opening) = net::join(addr)?;
+
+        Ok((
+            Self {
+                connection,
+                opening,
+            },
+            settings,
+        ))
+    }
+
+    /// Sends `message`, the opponent has left if that fails.
+    fn send(&mut self, sweeper: &mut Sweeper, message: &Message) {
+        if self.connection.send(message).is_err() {
+            sweeper.set_opponent(Opponent::Left);
+        }
+    }
+}
+
+/// How a game ended.
+enum Exit {
+    Quit,
+    Terminate,
+}
+
+struct Screen {
+    stdout: Stdout,
+    /// Raw mode, left when dropped. `None` when stdout is not a terminal.
+    raw: Option<RawTerminal<Stdout>>,
+    stdin: Keys<Input>,
+    size: (u16, u16),
+    alternate: bool,
+}
+
+impl Screen {
+    fn next_event(&mut self, keymap: &Keymap) -> Result<Option<Event>> {
+        self.stdout.lock().flush()?;
+
+        let input = self.stdin.next();
+        if let Some(Err(e)) = &input {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                return Ok(Some(Event::Terminate));
+            }
+        }
+
+        let signal = match input {
+            Some(Ok(key)) if keymap.action(&key) == Some(Action::Suspend) => Some(Signal::Suspend),
+            _ => terminal::pending_signal(),
+        };
+        match signal {
+            Some(Signal::Terminate) => return Ok(Some(Event::Terminate)),
+            Some(Signal::Suspend) => {
+                terminal::suspend();
+                if let Some(raw) = &self.raw {
+                    raw.activate_raw_mode()?;
+                }
+                if self.alternate {
+                    terminal::enter_alternate_screen(&mut self.stdout)?;
+                }
+                return Ok(Some(Event::Resume));
+            }
+            None => {}
+        }
+
+        if let Some(Ok(key)) = input {
+            return Ok(Some(Event::Key(key)));
+        }
+
+        let size = terminal::size();
+        if size != self.size {
+            self.size = size;
+            return Ok(Some(Event::Resize(size)));
+        }
+
+        Ok(None)
+    }
+}
+
+fn run(args: Args) -> Result<()> {
+    let keymap = Keymap::load(args.keymap.as_deref())?;
+    let theme = args.theme()?;
+    let settings = args.settings();
+
+    if let Some(addr) = &args.serve {
+        return serve(addr, settings.unwrap_or_default());
+    }
+
+    let puzzle = match &args.command {
+        Some(Command::Puzzle {
+            puzzle: Some(name), ..
+        }) => Some(load_puzzle(name)?),
+        Some(Command::Puzzle {
+            generate: Some(grade),
+            rows,
+            cols,
+            mines,
+            seed,
+            ..
+        }) => return generate_puzzle(*grade, *rows, *cols, *mines, *seed),
+        Some(Command::Puzzle { .. }) => {
+            for puzzle in puzzle::bundled() {
+                println!("{}", puzzle);
+            }
+            return Ok(());
+        }
+        _ => None,
     };
-    let custom_params = match (args.rows, args.cols, args.bomb_percentile) {
-        (Some(rows), Some(cols), Some(bomb_percentile)) => Some((rows, cols, bomb_percentile)),
+
+    let mut stats = Stats::load()?;
+    let coop_client = args.coop.as_deref().map(Client::connect).transpose()?;
+    let watch_client = match &args.command {
+        Some(Command::Watch { addr }) => Some(Client::connect(addr)?),
+        _ => None,
+    };
+    let editor = match &args.command {
+        Some(Command::Edit { file, rows, cols }) => {
+            let field = match layout::load(file).map_err(Error::Layout)? {
+                Some(field) => field,
+                None if *rows == 0 || *cols == 0 => {
+                    return Err(Error::InvalidDimensions {
+                        rows: *rows,
+                        cols: *cols,
+                    })
+                }
+                None => Field::from_bombs(*rows, *cols, &[]).with_rules(args.rules),
+            };
+            Some(Editor::new(
+                field,
+                file.clone(),
+                keymap.clone(),
+                theme.clone(),
+            ))
+        }
         _ => None,
     };
-    let mut sweeper = Sweeper::new(difficulty, custom_params);
-    let mut cursor = Position(sweeper.field.rows / 2, sweeper.field.cols / 2);
 
-    writeln!(stdout, "{}", termion::clear::All).unwrap();
+    // Used by --endless and by the menu's endless item alike.
+    let endless_density = match args.bomb_percentile {
+        Some(density) if !(density > 0.0 && density < 1.0) => {
+            return Err(Error::InvalidBombPercentile(density))
+        }
+        density => density.unwrap_or(endless::DEFAULT_DENSITY),
+    };
+    let mut race = match (&args.host, &args.join) {
+        (Some(addr), _) => Some(Race::host(addr, settings)?),
+        (None, Some(addr)) => Some(Race::join(addr)?),
+        (None, None) => None,
+    };
+    let settings = race
+        .as_ref()
+        .map_or(settings, |(_, settings)| Some(*settings));
+
+    // Fail on a bad board before touching the terminal.
+    if let Some(settings) = &settings {
+        Sweeper::from_settings(settings)?;
+    }
+
+    let mut publisher = match &args.publish {
+        Some(addr) => {
+            let publisher = Publisher::new(net::bind(addr)?)?;
+            eprintln!("publishing the game on {}", publisher.address());
+            Some(publisher)
+        }
+        None => None,
+    };
+
+    // Plain output may go to a file, there is no terminal to set up then.
+    let tty = !theme.is_plain() || terminal::is_tty();
+    if tty {
+        terminal::save()?;
+    }
+    terminal::install_panic_hook();
+    terminal::install_signal_handlers();
+
+    let mut screen = Screen {
+        stdout: stdout(),
+        raw: match tty {
+            true => Some(terminal::enter_raw_mode()?),
+            false => None,
+        },
+        stdin: Input::new(tty).keys(),
+        size: terminal::size(),
+        alternate: !theme.is_plain(),
+    };
+    let _restore_guard = RestoreGuard;
+
+    if screen.alternate {
+        terminal::enter_alternate_screen(&mut screen.stdout)?;
+    }
+
+    if let Some(client) = coop_client {
+        coop(&mut screen, &keymap, &theme, client)?;
+        return Ok(());
+    }
+
+    if let Some(client) = watch_client {
+        watch(&mut screen, &keymap, &theme, client)?;
+        return Ok(());
+    }
+
+    if let Some(editor) = editor {
+        edit(&mut screen, &keymap, &theme, editor)?;
+        return Ok(());
+    }
+
+    if let Some(Command::Tutorial) = &args.command {
+        tutorial(&mut screen, &keymap, &theme)?;
+        return Ok(());
+    }
+
+    if args.endless {
+        endless(&mut screen, &keymap, &theme, args.seed, endless_density)?;
+        return Ok(());
+    }
+
+    if let Some(puzzle) = puzzle {
+        let name = format!("{} ({})", puzzle.name, puzzle.grade.name());
+        play_layout(&mut screen, &keymap, &theme, &puzzle.field, Some(&name))?;
+        return Ok(());
+    }
+
+    if let Some(settings) = settings {
+        let race = race.as_mut().map(|(race, _)| race);
+        play(
+            &mut screen,
+            &keymap,
+            &theme,
+            &settings,
+            &mut stats,
+            race,
+            publisher.as_mut(),
+        )?;
+        return Ok(());
+    }
+
+    let mut menu = Menu::new(keymap.clone(), theme.clone())
+        .with_seed(args.seed)
+        .with_no_guess(args.no_guess)
+        .with_lives(args.lives)
+        .with_time_limit(args.time_limit())
+        .with_rules(args.rules)
+        .with_density(endless_density);
+    menu.display(&mut screen.stdout, &stats)?;
+
+    loop {
+        match screen.next_event(&keymap)? {
+            Some(Event::Terminate) => break,
+            Some(Event::Resume) | Some(Event::Resize(_)) => {
+                menu.redraw();
+                menu.display(&mut screen.stdout, &stats)?;
+            }
+            Some(Event::Key(key)) => {
+                match menu.tick(&key) {
+                    Some(MenuChoice::Quit) => break,
+                    Some(MenuChoice::Play(settings)) => {
+                        if let Exit::Terminate = play(
+                            &mut screen,
+                            &keymap,
+                            &theme,
+                            &settings,
+                            &mut stats,
+                            None,
+                            publisher.as_mut(),
+                        )? {
+                            break;
+                        }
+                        menu.redraw();
+                    }
+                    Some(MenuChoice::Endless(seed, density)) => {
+                        if let Exit::Terminate =
+                            endless(&mut screen, &keymap, &theme, seed, density)?
+                        {
+                            break;
+                        }
+                        menu.redraw();
+                    }
+                    Some(MenuChoice::Tutorial) => {
+                        if let Exit::Terminate = tutorial(&mut screen, &keymap, &theme)? {
+                            break;
+                        }
+                        menu.redraw();
+                    }
+                    None => {}
+                }
+                menu.display(&mut screen.stdout, &stats)?;
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Plays games with `settings` until the player quits, restarting on request
+/// and recording every finished game in `stats`. In a `race`, the board is
+/// opened at the agreed tile and progress is exchanged after every move. A
+/// `publisher` sends the board to anyone watching.
+fn play(
+    screen: &mut Screen,
+    keymap: &Keymap,
+    theme: &Theme,
+    settings: &GameSettings,
+    stats: &mut Stats,
+    mut race: Option<&mut Race>,
+    mut publisher: Option<&mut Publisher>,
+) -> Result<Exit> {
+    let new_sweeper = || -> Result<Sweeper> {
+        Ok(Sweeper::from_settings(settings)?
+            .with_keymap(keymap.clone())
+            .with_theme(theme.clone()))
+    };
+
+    let mut sweeper = new_sweeper()?;
+    let mut cursor = Position(sweeper.field.rows / 2, sweeper.field.cols / 2, 0);
+    let mut recorded = false;
 
+    if let Some(race) = race.as_mut() {
+        sweeper.reveal(&race.opening);
+        let progress = sweeper.progress();
+        sweeper.set_opponent(Opponent::Racing(progress));
+        race.send(&mut sweeper, &Message::Progress(progress));
+    }
+    let mut progress = sweeper.progress();
+
+    sweeper.resize(screen.size);
     sweeper.select(&cursor);
-    sweeper.display_field(&mut stdout);
+    sweeper.display_field(&mut screen.stdout)?;
+    if let Some(publisher) = publisher.as_mut() {
+        publisher.publish(&sweeper, &cursor);
+    }
 
     loop {
-        let input = stdin.next();
+        if let Some(publisher) = publisher.as_mut() {
+            publisher.accept()?;
+        }
 
-        if let Some(Ok(key)) = input {
-            let (should_exit, should_restart, updated_cursor) = sweeper.tick(&key, cursor);
-            if should_exit {
-                break;
+        if let Some(race) = race.as_mut() {
+            while let Some(message) = race.connection.poll()? {
+                match message {
+                    Message::Progress(progress) => sweeper.set_opponent(Opponent::Racing(progress)),
+                    Message::Bye => sweeper.set_opponent(Opponent::Left),
+                    Message::Start { .. } => {}
+                }
+                sweeper.display_field(&mut screen.stdout)?;
+            }
+        }
+
+        // A countdown is redrawn every second, keys or not.
+        let ticked = sweeper.tick_clock();
+        if ticked {
+            sweeper.display_field(&mut screen.stdout)?;
+        }
+
+        let event = screen.next_event(keymap)?;
+        let handled = event.is_some() || ticked;
+        match event {
+            Some(Event::Terminate) => {
+                if let Some(race) = race.as_mut() {
+                    race.send(&mut sweeper, &Message::Bye);
+                }
+                return Ok(Exit::Terminate);
+            }
+            Some(Event::Resume) => {
+                sweeper.redraw();
+                sweeper.display_field(&mut screen.stdout)?;
+            }
+            Some(Event::Resize(size)) => {
+                sweeper.resize(size);
+                sweeper.select(&cursor);
+                sweeper.display_field(&mut screen.stdout)?;
+            }
+            Some(Event::Key(key)) => {
+                let (should_exit, should_restart, updated_cursor) = sweeper.tick(&key, cursor);
+                if should_exit {
+                    if let Some(race) = race.as_mut() {
+                        race.send(&mut sweeper, &Message::Bye);
+                    }
+                    return Ok(Exit::Quit);
+                }
+
+                if should_restart {
+                    sweeper = new_sweeper()?;
+                    sweeper.resize(screen.size);
+                    recorded = false;
+                }
+
+                // A time attack goes straight on with the next board.
+                if sweeper.is_attack()
+                    && !sweeper.is_timed_out()
+                    && sweeper.state() != GameState::Playing
+                {
+                    sweeper = sweeper.next_board(new_sweeper()?);
+                    sweeper.resize(screen.size);
+                    sweeper.select(&updated_cursor);
+                }
+
+                if let Some(race) = race.as_mut() {
+                    if sweeper.progress() != progress {
+                        progress = sweeper.progress();
+                        race.send(&mut sweeper, &Message::Progress(progress));
+                    }
+                }
+
+                cursor = updated_cursor;
+                sweeper.display_field(&mut screen.stdout)?;
+            }
+            None => {}
+        }
+
+        if handled && !recorded && sweeper.state() != GameState::Playing {
+            if let Some(key) = settings.stats_key() {
+                let won = sweeper.state() == GameState::Won;
+                match settings.daily {
+                    Some(date) => stats.add_daily(date, won, sweeper.elapsed()),
+                    None => stats.add_game(&key, won, sweeper.elapsed()),
+                }
+                stats.save()?;
+            }
+            recorded = true;
+        }
+
+        if let (true, Some(publisher)) = (handled, publisher.as_mut()) {
+            publisher.publish(&sweeper, &cursor);
+        }
+    }
+}
+
+/// A bundled puzzle, or the one in the layout file `name`.
+fn load_puzzle(name: &str) -> Result<Puzzle> {
+    if let Some(puzzle) = puzzle::find(name) {
+        return Ok(puzzle);
+    }
+
+    match layout::load(Path::new(name)).map_err(Error::Layout)? {
+        Some(field) => Puzzle::new(name, field).map_err(Error::Layout),
+        None => Err(Error::Layout(format!(
+            "`{}` is neither a bundled puzzle nor a layout file",
+            name
+        ))),
+    }
+}
+
+/// Prints a new puzzle of `grade` as a layout.
+fn generate_puzzle(
+    grade: Grade,
+    rows: usize,
+    cols: usize,
+    mines: usize,
+    seed: Option<u64>,
+) -> Result<()> {
+    Field::validate(rows, cols, mines)?;
+
+    let seed = seed.unwrap_or_else(rand::random);
+    let puzzle = puzzle::generate(rows, cols, mines, grade, seed).ok_or_else(|| {
+        Error::Layout(format!(
+            "no {} puzzle among {} boards of {}x{} with {} mines",
+            grade.name(),
+            puzzle::GENERATE_ATTEMPTS,
+            rows,
+            cols,
+            mines
+        ))
+    })?;
+
+    print!(
+        "# {} puzzle, {}\n{}",
+        grade.name(),
+        puzzle.name,
+        layout::format(&puzzle.field)
+    );
+    Ok(())
+}
+
+/// Plays `field` as laid out, as the puzzle `puzzle` if given, without
+/// counting it in the stats.
+fn play_layout(
+    screen: &mut Screen,
+    keymap: &Keymap,
+    theme: &Theme,
+    field: &Field,
+    puzzle: Option<&str>,
+) -> Result<Exit> {
+    let new_sweeper = |size| {
+        let sweeper = Sweeper::from_field(Difficulty::Custom, field.clone())
+            .with_keymap(keymap.clone())
+            .with_theme(theme.clone());
+        let mut sweeper = match puzzle {
+            Some(name) => sweeper.with_puzzle(name),
+            None => sweeper,
+        };
+        sweeper.resize(size);
+        sweeper
+    };
+
+    let mut sweeper = new_sweeper(screen.size);
+    let mut cursor = Position(field.rows / 2, field.cols / 2, 0);
+    sweeper.select(&cursor);
+    sweeper.display_field(&mut screen.stdout)?;
+
+    loop {
+        match screen.next_event(keymap)? {
+            Some(Event::Terminate) => return Ok(Exit::Terminate),
+            Some(Event::Resume) => sweeper.redraw(),
+            Some(Event::Resize(size)) => {
+                sweeper.resize(size);
+                sweeper.select(&cursor);
+            }
+            Some(Event::Key(key)) => {
+                let (should_exit, should_restart, updated_cursor) = sweeper.tick(&key, cursor);
+                if should_exit {
+                    return Ok(Exit::Quit);
+                }
+
+                cursor = updated_cursor;
+                if should_restart {
+                    sweeper = new_sweeper(screen.size);
+                    sweeper.select(&cursor);
+                }
+            }
+            None => continue,
+        }
+        sweeper.display_field(&mut screen.stdout)?;
+    }
+}
+
+/// A screen that plays keys on its own until the player leaves it, see
+/// `run_mode`.
+trait Mode {
+    fn display(&mut self, stdout: &mut Stdout) -> io::Result<()>;
+    fn redraw(&mut self);
+    fn resize(&mut self, size: (u16, u16));
+    /// Plays `key`, returning whether to leave.
+    fn tick(&mut self, key: &Key) -> bool;
+}
+
+impl Mode for Tutorial {
+    fn display(&mut self, stdout: &mut Stdout) -> io::Result<()> {
+        Tutorial::display(self, stdout)
+    }
+
+    fn redraw(&mut self) {
+        Tutorial::redraw(self)
+    }
+
+    fn resize(&mut self, size: (u16, u16)) {
+        Tutorial::resize(self, size)
+    }
+
+    fn tick(&mut self, key: &Key) -> bool {
+        Tutorial::tick(self, key)
+    }
+}
+
+impl Mode for Endless {
+    fn display(&mut self, stdout: &mut Stdout) -> io::Result<()> {
+        Endless::display(self, stdout)
+    }
+
+    fn redraw(&mut self) {
+        Endless::redraw(self)
+    }
+
+    fn resize(&mut self, size: (u16, u16)) {
+        Endless::resize(self, size)
+    }
+
+    fn tick(&mut self, key: &Key) -> bool {
+        Endless::tick(self, key)
+    }
+}
+
+/// Runs `mode` until the player leaves it.
+fn run_mode(screen: &mut Screen, keymap: &Keymap, mode: &mut dyn Mode) -> Result<Exit> {
+    mode.display(&mut screen.stdout)?;
+
+    loop {
+        match screen.next_event(keymap)? {
+            Some(Event::Terminate) => return Ok(Exit::Terminate),
+            Some(Event::Resume) => mode.redraw(),
+            Some(Event::Resize(size)) => mode.resize(size),
+            Some(Event::Key(key)) => {
+                if mode.tick(&key) {
+                    return Ok(Exit::Quit);
+                }
             }
+            None => continue,
+        }
+        mode.display(&mut screen.stdout)?;
+    }
+}
 
-            if should_restart {
-                sweeper = Sweeper::new(difficulty, custom_params);
+/// Runs the tutorial until the player leaves it.
+fn tutorial(screen: &mut Screen, keymap: &Keymap, theme: &Theme) -> Result<Exit> {
+    let mut tutorial = Tutorial::new(keymap.clone(), theme.clone(), screen.size);
+    run_mode(screen, keymap, &mut tutorial)
+}
+
+/// Plays an endless board until the player leaves it.
+fn endless(
+    screen: &mut Screen,
+    keymap: &Keymap,
+    theme: &Theme,
+    seed: Option<u64>,
+    density: f32,
+) -> Result<Exit> {
+    let seed = seed.unwrap_or_else(rand::random);
+    let mut endless = Endless::new(seed, density, keymap.clone(), theme.clone(), screen.size);
+    run_mode(screen, keymap, &mut endless)
+}
+
+/// Edits a board layout, trying it out whenever asked to.
+fn edit(screen: &mut Screen, keymap: &Keymap, theme: &Theme, mut editor: Editor) -> Result<()> {
+    editor.resize(screen.size);
+    editor.display(&mut screen.stdout)?;
+
+    loop {
+        match screen.next_event(keymap)? {
+            Some(Event::Terminate) => break,
+            Some(Event::Resume) => editor.redraw(),
+            Some(Event::Resize(size)) => editor.resize(size),
+            Some(Event::Key(key)) => match editor.tick(&key) {
+                Some(EditorChoice::Quit) => break,
+                Some(EditorChoice::Play(field)) => {
+                    if let Exit::Terminate = play_layout(screen, keymap, theme, &field, None)? {
+                        break;
+                    }
+                    editor.redraw();
+                }
+                None => {}
+            },
+            None => continue,
+        }
+        editor.display(&mut screen.stdout)?;
+    }
+
+    Ok(())
+}
+
+/// Runs a co-op server until the process is stopped.
+fn serve(addr: &str, settings: GameSettings) -> Result<()> {
+    let sweeper = Sweeper::from_settings(&settings)?;
+    let listener = net::listen(addr)?;
+
+    eprintln!("serving a co-op game on {}", listener.local_addr()?);
+    Server::new(listener, sweeper)?.run()
+}
+
+/// Plays on a co-op server: moving the cursor is local, revealing and
+/// flagging are sent to the server, which sends back the board.
+fn coop(screen: &mut Screen, keymap: &Keymap, theme: &Theme, mut client: Client) -> Result<()> {
+    let mut sweeper: Option<Sweeper> = None;
+    let mut cursor = Position(0, 0, 0);
+
+    loop {
+        if client.poll()? {
+            if client.closed {
+                return Err(Error::Network("the server closed the game".to_string()));
             }
 
-            cursor = updated_cursor;
-            sweeper.display_field(&mut stdout);
+            if let (Some(board), Some(overlay)) = (&client.board, client.overlay()) {
+                let field = board.to_field();
+                let current = sweeper.get_or_insert_with(|| {
+                    cursor = overlay
+                        .cursors
+                        .get(&overlay.me)
+                        .cloned()
+                        .unwrap_or(Position(0, 0, 0));
+                    let mut sweeper = Sweeper::from_field(Difficulty::Custom, field.clone())
+                        .with_keymap(keymap.clone())
+                        .with_theme(theme.clone());
+                    sweeper.resize(screen.size);
+                    sweeper
+                });
+
+                current.field = field;
+                current.set_overlay(overlay);
+                current.select(&cursor);
+                current.display_field(&mut screen.stdout)?;
+            }
+        }
+
+        // Keys are read before the first board arrives too, to quit waiting.
+        let event = screen.next_event(keymap)?;
+        if is_quit(&event, keymap) {
+            break;
+        }
+        let sweeper = match sweeper.as_mut() {
+            Some(sweeper) => sweeper,
+            None => continue,
+        };
+
+        match event {
+            Some(Event::Resume) => sweeper.redraw(),
+            Some(Event::Resize(size)) => {
+                sweeper.resize(size);
+                sweeper.select(&cursor);
+            }
+            Some(Event::Key(key)) => match keymap.action(&key) {
+                Some(Action::Reveal) => client.send(&ClientMessage::Reveal(cursor.clone()))?,
+                Some(Action::Flag) => client.send(&ClientMessage::Flag(cursor.clone()))?,
+                Some(Action::Restart) => {}
+                _ => {
+                    let updated_cursor = sweeper.tick(&key, cursor.clone()).2;
+                    if updated_cursor != cursor {
+                        client.send(&ClientMessage::Cursor(updated_cursor.clone()))?;
+                    }
+                    cursor = updated_cursor;
+                }
+            },
+            Some(Event::Terminate) | None => continue,
+        }
+        sweeper.display_field(&mut screen.stdout)?;
+    }
+
+    let _ = client.send(&ClientMessage::Bye);
+    Ok(())
+}
+
+/// Whether `event` ends a game that is only watched or played remotely.
+fn is_quit(event: &Option<Event>, keymap: &Keymap) -> bool {
+    match event {
+        Some(Event::Terminate) => true,
+        Some(Event::Key(key)) => keymap.action(key) == Some(Action::Quit),
+        _ => false,
+    }
+}
+
+/// Shows a published game as it is played, until it ends or the watcher quits.
+fn watch(screen: &mut Screen, keymap: &Keymap, theme: &Theme, mut client: Client) -> Result<()> {
+    let mut sweeper: Option<Sweeper> = None;
+
+    loop {
+        if client.poll()? {
+            if client.closed {
+                return Err(Error::Network(
+                    "the game is no longer published".to_string(),
+                ));
+            }
+
+            if let Some(board) = &client.board {
+                let field = board.to_field();
+                let current = sweeper.get_or_insert_with(|| {
+                    let mut sweeper = Sweeper::from_field(Difficulty::Custom, field.clone())
+                        .with_keymap(keymap.clone())
+                        .with_theme(theme.clone());
+                    sweeper.set_watching();
+                    sweeper.resize(screen.size);
+                    sweeper
+                });
+
+                current.field = field;
+                if let Some(cursor) = client.cursors.get(&watch::PLAYER) {
+                    current.select(cursor);
+                }
+                current.display_field(&mut screen.stdout)?;
+            }
+        }
+
+        let event = screen.next_event(keymap)?;
+        if is_quit(&event, keymap) {
+            return Ok(());
+        }
+        let sweeper = match sweeper.as_mut() {
+            Some(sweeper) => sweeper,
+            None => continue,
+        };
+
+        match event {
+            Some(Event::Resume) => sweeper.redraw(),
+            Some(Event::Resize(size)) => {
+                sweeper.resize(size);
+                if let Some(cursor) = client.cursors.get(&watch::PLAYER) {
+                    sweeper.select(cursor);
+                }
+            }
+            _ => continue,
         }
+        sweeper.display_field(&mut screen.stdout)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_rules_are_not_published() {
+        let parse = |args: &[&str]| Args::try_parse_from([&["rust-sweeper"], args].concat());
 
-        stdout.lock().flush().unwrap();
+        // Watchers only know single mines and plain flags, and would see the
+        // true numbers behind lies and hidden ones.
+        assert!(parse(&["--rules", "multi-mine:9", "--publish"]).is_err());
+        assert!(parse(&["--rules", "liar", "--publish"]).is_err());
+        assert!(parse(&["--rules", "hidden-numbers", "--publish"]).is_err());
+        assert!(parse(&["--publish"]).is_ok());
     }
 }
@@ -0,0 +1,451 @@
+use std::io::{self, Write};
+
+use termion::event::Key;
+
+use crate::daily::{self, Date};
+use crate::endless;
+use crate::field::Field;
+use crate::keymap::{Action, Keymap};
+use crate::renderer::{Cell, Frame, Renderer};
+use crate::rules::RuleSet;
+use crate::stats::Stats;
+use crate::sweeper::{Bombs, CustomParams, Difficulty, GameSettings, TimeLimit};
+use crate::theme::Theme;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    Play(Difficulty),
+    Daily,
+    Endless,
+    Rows,
+    Cols,
+    Mines,
+    Seed,
+    NoGuess,
+    Lives,
+    Stats,
+    Tutorial,
+    Quit,
+}
+
+const ITEMS: [Item; 16] = [
+    Item::Play(Difficulty::Easy),
+    Item::Play(Difficulty::Medium),
+    Item::Play(Difficulty::Hard),
+    Item::Play(Difficulty::Nightmare),
+    Item::Play(Difficulty::Custom),
+    Item::Rows,
+    Item::Cols,
+    Item::Mines,
+    Item::Daily,
+    Item::Endless,
+    Item::Seed,
+    Item::NoGuess,
+    Item::Lives,
+    Item::Stats,
+    Item::Tutorial,
+    Item::Quit,
+];
+
+/// Digits accepted in the custom board fields.
+const MAX_DIGITS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuChoice {
+    Play(GameSettings),
+    /// An endless board from this seed, or a random one, with this share of
+    /// mines.
+    Endless(Option<u64>, f32),
+    Tutorial,
+    Quit,
+}
+
+/// The start screen: pick a difficulty or a custom board, the seed and
+/// no-guess options, or look at the stats.
+#[derive(Debug)]
+pub struct Menu {
+    keymap: Keymap,
+    theme: Theme,
+    renderer: Renderer,
+    selected: usize,
+    rows: String,
+    cols: String,
+    mines: String,
+    /// Empty for a random seed.
+    seed: String,
+    no_guess: bool,
+    /// Empty for a regular game.
+    lives: String,
+    /// From the command line, the menu does not change these.
+    time_limit: Option<TimeLimit>,
+    rules: RuleSet,
+    /// Share of mines on the endless board.
+    density: f32,
+    showing_stats: bool,
+    today: Date,
+}
+
+impl Menu {
+    pub fn new(keymap: Keymap, theme: Theme) -> Self {
+        Self {
+            keymap,
+            theme,
+            renderer: Renderer::default(),
+            selected: 0,
+            rows: "16".to_string(),
+            cols: "16".to_string(),
+            mines: "40".to_string(),
+            seed: String::new(),
+            no_guess: false,
+            lives: String::new(),
+            time_limit: None,
+            rules: RuleSet::Classic,
+            density: endless::DEFAULT_DENSITY,
+            showing_stats: false,
+            today: Date::today(),
+        }
+    }
+
+    pub fn with_seed(self, seed: Option<u64>) -> Self {
+        Self {
+            seed: seed.map_or(String::new(), |seed| seed.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_no_guess(self, no_guess: bool) -> Self {
+        Self { no_guess, ..self }
+    }
+
+    pub fn with_lives(self, lives: Option<u32>) -> Self {
+        Self {
+            lives: lives.map_or(String::new(), |lives| lives.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_time_limit(self, time_limit: Option<TimeLimit>) -> Self {
+        Self { time_limit, ..self }
+    }
+
+    pub fn with_rules(self, rules: RuleSet) -> Self {
+        Self { rules, ..self }
+    }
+
+    pub fn with_density(self, density: f32) -> Self {
+        Self { density, ..self }
+    }
+
+    /// Draws the whole menu on the next `display`, e.g. after a game.
+    pub fn redraw(&mut self) {
+        self.renderer.invalidate();
+    }
+
+    /// The custom board, or why it cannot be played.
+    fn custom_params(&self) -> Result<CustomParams, String> {
+        let parse = |name: &str, value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("{} must be a number", name))
+        };
+        let rows = parse("rows", &self.rows)?;
+        let cols = parse("cols", &self.cols)?;
+        let mines = parse("mines", &self.mines)?;
+
+        Field::validate(rows, cols, mines).map_err(|e| e.to_string())?;
+
+        Ok(CustomParams {
+            layers: 1,
+            rows,
+            cols,
+            bombs: Bombs::Count(mines),
+        })
+    }
+
+    fn settings(&self, difficulty: Difficulty) -> Option<GameSettings> {
+        let custom_params = match difficulty {
+            Difficulty::Custom => Some(self.custom_params().ok()?),
+            _ => None,
+        };
+
+        Some(GameSettings {
+            difficulty,
+            custom_params,
+            seed: self.seed.parse().ok(),
+            no_guess: self.no_guess,
+            daily: None,
+            lives: self.lives.parse().ok().filter(|&lives| lives > 0),
+            time_limit: self.time_limit,
+            rules: self.rules,
+        })
+    }
+
+    fn text_field(&mut self, item: Item) -> Option<&mut String> {
+        match item {
+            Item::Rows => Some(&mut self.rows),
+            Item::Cols => Some(&mut self.cols),
+            Item::Mines => Some(&mut self.mines),
+            Item::Seed => Some(&mut self.seed),
+            Item::Lives => Some(&mut self.lives),
+            _ => None,
+        }
+    }
+
+    pub fn tick(&mut self, key: &Key) -> Option<MenuChoice> {
+        if self.showing_stats {
+            self.showing_stats = false;
+            return None;
+        }
+
+        let item = ITEMS[self.selected];
+        if let Some(text) = self.text_field(item) {
+            match key {
+                Key::Char(digit @ '0'..='9') => {
+                    let edited = format!("{}{}", text, digit);
+                    let fits = match item {
+                        Item::Seed => edited.parse::<u64>().is_ok(),
+                        _ => edited.len() <= MAX_DIGITS,
+                    };
+                    if fits {
+                        *text = edited;
+                    }
+                    return None;
+                }
+                Key::Backspace => {
+                    text.pop();
+                    return None;
+                }
+                _ => {}
+            }
+        }
+
+        let action = match key {
+            Key::Char('\n') => Some(Action::Reveal),
+            _ => self.keymap.action(key),
+        };
+
+        match action {
+            Some(Action::MoveUp) => self.selected = self.selected.saturating_sub(1),
+            Some(Action::MoveDown) => self.selected = (self.selected + 1).min(ITEMS.len() - 1),
+            Some(Action::Top) => self.selected = 0,
+            Some(Action::Bottom) => self.selected = ITEMS.len() - 1,
+            Some(Action::Quit) => return Some(MenuChoice::Quit),
+            Some(Action::Reveal) => match item {
+                Item::Play(difficulty) => return self.settings(difficulty).map(MenuChoice::Play),
+                Item::Daily => return Some(MenuChoice::Play(GameSettings::daily(self.today))),
+                Item::Endless => {
+                    return Some(MenuChoice::Endless(self.seed.parse().ok(), self.density))
+                }
+                Item::NoGuess => self.no_guess = !self.no_guess,
+                Item::Stats => self.showing_stats = true,
+                Item::Tutorial => return Some(MenuChoice::Tutorial),
+                Item::Quit => return Some(MenuChoice::Quit),
+                Item::Rows | Item::Cols | Item::Mines | Item::Seed | Item::Lives => {
+                    self.selected = (self.selected + 1).min(ITEMS.len() - 1)
+                }
+            },
+            _ => {}
+        }
+
+        None
+    }
+
+    fn line(&self, text: String) -> Vec<Cell> {
+        vec![Cell::new(
+            text.chars().count(),
+            format!("{}{}", self.theme.text(), text),
+        )]
+    }
+
+    fn describe(&self, item: Item, stats: &Stats) -> (String, String) {
+        match item {
+            Item::Play(Difficulty::Custom) => (
+                "Custom".to_string(),
+                match self.custom_params() {
+                    Ok(CustomParams { rows, cols, .. }) => {
+                        format!("{}x{}, {} mines", rows, cols, self.mines)
+                    }
+                    Err(e) => e,
+                },
+            ),
+            Item::Play(difficulty) => {
+                let (rows, cols, bomb_percentile) = difficulty.board().unwrap_or_default();
+                let mines = Bombs::Percentile(bomb_percentile).count(rows, cols);
+                let name = difficulty.name();
+
+                (
+                    name[..1].to_uppercase() + &name[1..],
+                    format!("{}x{}, {} mines", rows, cols, mines),
+                )
+            }
+            Item::Daily => (
+                "Daily".to_string(),
+                format!(
+                    "{}, {}, streak {}",
+                    self.today,
+                    daily::DIFFICULTY.name(),
+                    stats.streak(self.today)
+                ),
+            ),
+            Item::Endless => (
+                "Endless".to_string(),
+                format!(
+                    "no edges, {:.0}% mines, clear as much as you can",
+                    self.density * 100.0
+                ),
+            ),
+            Item::Rows => ("  Rows".to_string(), self.rows.clone()),
+            Item::Cols => ("  Cols".to_string(), self.cols.clone()),
+            Item::Mines => ("  Mines".to_string(), self.mines.clone()),
+            Item::Seed => (
+                "Seed".to_string(),
+                match self.seed.is_empty() {
+                    true => "random".to_string(),
+                    false => self.seed.clone(),
+                },
+            ),
+            Item::NoGuess => (
+                "No guessing".to_string(),
+                match self.no_guess {
+                    true => "on".to_string(),
+                    false => "off".to_string(),
+                },
+            ),
+            Item::Lives => (
+                "Lives".to_string(),
+                match self.lives.parse::<u32>() {
+                    Ok(lives) if lives > 0 => format!("{}, not counted in the stats", lives),
+                    _ => "off".to_string(),
+                },
+            ),
+            Item::Stats => ("Stats".to_string(), String::new()),
+            Item::Tutorial => ("Tutorial".to_string(), "learn the rules".to_string()),
+            Item::Quit => ("Quit".to_string(), String::new()),
+        }
+    }
+
+    fn render_items(&self, stats: &Stats) -> Frame {
+        let mut lines = vec![self.line(" rust-sweeper".to_string()), vec![]];
+
+        lines.extend(ITEMS.iter().enumerate().map(|(index, item)| {
+            let (name, detail) = self.describe(*item, stats);
+            let marker = match index == self.selected {
+                true => '>',
+                false => ' ',
+            };
+            self.line(format!(" {} {:<14}{}", marker, name, detail))
+        }));
+
+        lines.push(vec![]);
+        lines.push(self.line(format!(
+            " {}/{}: move, enter: select, digits and backspace: edit, {}: quit",
+            self.keymap.key_hint(Action::MoveUp),
+            self.keymap.key_hint(Action::MoveDown),
+            self.keymap.key_hint(Action::Quit)
+        )));
+
+        lines
+    }
+
+    fn render_stats(&self, stats: &Stats) -> Frame {
+        let mut lines = vec![
+            self.line(" Stats".to_string()),
+            vec![],
+            self.line(format!(
+                "   {:<12}{:>8}{:>8}{:>8}",
+                "", "played", "won", "best"
+            )),
+        ];
+
+        lines.extend(ITEMS.iter().filter_map(|item| match item {
+            Item::Play(difficulty) => {
+                let record = stats.record(difficulty.name());
+                Some(self.line(format!(
+                    "   {:<12}{:>8}{:>8}{:>8}",
+                    difficulty.name(),
+                    record.played,
+                    record.won,
+                    record.best.map_or("-".to_string(), |best| format!("{}s", best))
+                )))
+            }
+            _ => None,
+        }));
+
+        let today = stats.record(&self.today.stats_key());
+        lines.push(vec![]);
+        lines.push(self.line(format!(
+            "   daily {}: {}, streak {}",
+            self.today,
+            match (today.won, today.best) {
+                (0, _) | (_, None) => "not won yet".to_string(),
+                (_, Some(best)) => format!("won in {}s", best),
+            },
+            stats.streak(self.today)
+        )));
+
+        lines.push(vec![]);
+        lines.push(self.line(" press any key to go back".to_string()));
+
+        lines
+    }
+
+    pub fn display<W: Write>(&mut self, stdout: &mut W, stats: &Stats) -> io::Result<()> {
+        let frame = match self.showing_stats {
+            true => self.render_stats(stats),
+            false => self.render_items(stats),
+        };
+
+        match self.theme.is_plain() {
+            true => Renderer::draw_plain(stdout, frame),
+            false => self.renderer.draw(stdout, frame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_menu_custom_board() {
+        let mut menu = Menu::new(Keymap::default(), Theme::ascii());
+
+        for key in [Key::Down; 7] {
+            assert_eq!(menu.tick(&key), None);
+        }
+        // Mines: 40 -> 4 -> 400, too many for 16x16.
+        menu.tick(&Key::Backspace);
+        menu.tick(&Key::Char('0'));
+        menu.tick(&Key::Char('0'));
+        assert!(menu.custom_params().is_err());
+
+        for key in [Key::Up, Key::Up, Key::Up, Key::Char('\n')] {
+            assert_eq!(menu.tick(&key), None);
+        }
+
+        menu.selected = 7;
+        menu.tick(&Key::Backspace);
+        menu.tick(&Key::Backspace);
+        for key in [Key::Up, Key::Up, Key::Up] {
+            menu.tick(&key);
+        }
+        assert_eq!(
+            menu.tick(&Key::Char('\n')),
+            Some(MenuChoice::Play(GameSettings {
+                difficulty: Difficulty::Custom,
+                custom_params: Some(CustomParams {
+                    layers: 1,
+                    rows: 16,
+                    cols: 16,
+                    bombs: Bombs::Count(4),
+                }),
+                seed: None,
+                no_guess: false,
+                daily: None,
+                lives: None,
+                time_limit: None,
+                rules: RuleSet::Classic,
+            }))
+        );
+    }
+}
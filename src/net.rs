@@ -0,0 +1,482 @@
+//! A small line-based TCP protocol for racing another player on the same
+//! board. The host sends one `start` line, then both ends send `progress`
+//! after every move and `bye` when they leave:
+//!
+//! ```text
+//! start <difficulty> <rows> <cols> <mines> <seed> <no-guess 0|1> <row> <col>
+//! progress <percent> <playing|won|lost> <secs>
+//! bye
+//! ```
+//!
+//! The board itself never crosses the wire: both ends deal it from the same
+//! settings and seed and open it at the same tile.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+use crate::error::{Error, Result};
+use crate::rules::RuleSet;
+use crate::sweeper::{
+    Bombs, CustomParams, Difficulty, GameSettings, GameState, Position, Progress,
+};
+use crate::terminal;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Messages that travel as single lines of text.
+pub trait Wire: Sized {
+    /// The line to send, newline included.
+    fn to_line(&self) -> String;
+    fn parse(line: &str) -> std::result::Result<Self, String>;
+    /// What a closed connection reads as.
+    fn closed() -> Self;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// The board to race on and the tile both players start from.
+    Start {
+        settings: GameSettings,
+        opening: Position,
+    },
+    Progress(Progress),
+    Bye,
+}
+
+fn state_name(state: GameState) -> &'static str {
+    match state {
+        GameState::Playing => "playing",
+        GameState::Won => "won",
+        GameState::Lost => "lost",
+    }
+}
+
+impl Wire for Message {
+    fn to_line(&self) -> String {
+        match self {
+            Message::Start { settings, opening } => {
+                let (rows, cols, mines) = settings.custom_params.map_or((0, 0, 0), |params| {
+                    (
+                        params.rows,
+                        params.cols,
+                        params.bombs.count(params.rows, params.cols),
+                    )
+                });
+                format!(
+                    "start {} {} {} {} {} {} {} {}\n",
+                    settings.difficulty.name(),
+                    rows,
+                    cols,
+                    mines,
+                    settings.seed.unwrap_or(0),
+                    settings.no_guess as u8,
+                    opening.0,
+                    opening.1
+                )
+            }
+            Message::Progress(progress) => format!(
+                "progress {} {} {}\n",
+                progress.percent,
+                state_name(progress.state),
+                progress.secs
+            ),
+            Message::Bye => "bye\n".to_string(),
+        }
+    }
+
+    fn parse(line: &str) -> std::result::Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |index: usize| -> std::result::Result<u64, String> {
+            words
+                .get(index)
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| format!("bad message `{}`", line.trim()))
+        };
+
+        match words.first() {
+            Some(&"start") if words.len() == 9 => {
+                let difficulty = match words[1] {
+                    "custom" => Difficulty::Custom,
+                    name => Difficulty::parse(name).map_err(|e| e.to_string())?,
+                };
+                let custom_params = match difficulty {
+                    Difficulty::Custom => Some(CustomParams {
+                        layers: 1,
+                        rows: number(2)? as usize,
+                        cols: number(3)? as usize,
+                        bombs: Bombs::Count(number(4)? as usize),
+                    }),
+                    _ => None,
+                };
+
+                Ok(Message::Start {
+                    settings: GameSettings {
+                        difficulty,
+                        custom_params,
+                        seed: Some(number(5)?),
+                        no_guess: number(6)? == 1,
+                        daily: None,
+                        lives: None,
+                        time_limit: None,
+                        rules: RuleSet::Classic,
+                    },
+                    opening: Position(number(7)? as usize, number(8)? as usize, 0),
+                })
+            }
+            Some(&"progress") if words.len() == 4 => Ok(Message::Progress(Progress {
+                percent: number(1)?.min(100) as u8,
+                state: match words[2] {
+                    "playing" => GameState::Playing,
+                    "won" => GameState::Won,
+                    "lost" => GameState::Lost,
+                    _ => return Err(format!("bad message `{}`", line.trim())),
+                },
+                secs: number(3)?,
+            })),
+            Some(&"bye") => Ok(Message::Bye),
+            _ => Err(format!("bad message `{}`", line.trim())),
+        }
+    }
+
+    fn closed() -> Self {
+        Message::Bye
+    }
+}
+
+/// A TCP or Unix socket.
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Self {
+        Stream::Tcp(stream)
+    }
+}
+
+impl From<UnixStream> for Stream {
+    fn from(stream: UnixStream) -> Self {
+        Stream::Unix(stream)
+    }
+}
+
+/// Accepts `Stream`s on a TCP address or a Unix socket path, see `bind`.
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    /// The socket file is removed again on drop.
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => listener.set_nonblocking(nonblocking),
+            Listener::Unix(listener, _) => listener.set_nonblocking(nonblocking),
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| stream.into()),
+            Listener::Unix(listener, _) => listener.accept().map(|(stream, _)| stream.into()),
+        }
+    }
+
+    /// Where to connect to, for the user.
+    pub fn address(&self) -> String {
+        match self {
+            Listener::Tcp(listener) => listener
+                .local_addr()
+                .map_or_else(|_| "?".to_string(), |addr| addr.to_string()),
+            Listener::Unix(_, path) => path.display().to_string(),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// One end of a connection that sends `S` and receives `R`. Reads never
+/// block once `set_nonblocking` has been called.
+#[derive(Debug)]
+pub struct Connection<S: Wire = Message, R: Wire = Message> {
+    stream: Stream,
+    buffer: Vec<u8>,
+    closed: bool,
+    messages: PhantomData<(S, R)>,
+}
+
+impl<S: Wire, R: Wire> Connection<S, R> {
+    pub fn new(stream: impl Into<Stream>) -> io::Result<Self> {
+        let stream = stream.into();
+        if let Stream::Tcp(stream) = &stream {
+            stream.set_nodelay(true)?;
+        }
+        Ok(Self {
+            stream,
+            buffer: vec![],
+            closed: false,
+            messages: PhantomData,
+        })
+    }
+
+    pub fn set_nonblocking(&self) -> io::Result<()> {
+        self.stream.set_nonblocking(true)
+    }
+
+    pub fn send(&mut self, message: &S) -> io::Result<()> {
+        self.stream.write_all(message.to_line().as_bytes())
+    }
+
+    /// The next complete message, if one has arrived. A closed connection
+    /// reads as a single `R::closed()`, and malformed lines are logged and
+    /// skipped.
+    pub fn poll(&mut self) -> Result<Option<R>> {
+        let mut chunk = [0; 512];
+
+        if self.closed {
+            return Ok(None);
+        }
+
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                match R::parse(&String::from_utf8_lossy(&line)) {
+                    Ok(message) => return Ok(Some(message)),
+                    Err(e) => terminal::log(&format!("skipping a malformed message: {}", e)),
+                }
+                continue;
+            }
+
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.closed = true;
+                    return Ok(Some(R::closed()));
+                }
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::Network(e.to_string())),
+            }
+        }
+    }
+}
+
+/// The host and port in `addr`, with the default port if it has none. An
+/// IPv6 address takes a port only in brackets, as in `[::1]:7878`.
+pub fn with_port(addr: &str) -> Result<(String, u16)> {
+    if let Ok(addr) = addr.parse::<SocketAddr>() {
+        return Ok((addr.ip().to_string(), addr.port()));
+    }
+    let bare = addr.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = bare.parse::<IpAddr>() {
+        return Ok((ip.to_string(), DEFAULT_PORT));
+    }
+
+    match addr.rsplit_once(':') {
+        Some((host, port)) => match port.parse() {
+            Ok(port) => Ok((host.to_string(), port)),
+            Err(_) => Err(Error::Network(format!("bad port in {}", addr))),
+        },
+        None => Ok((addr.to_string(), DEFAULT_PORT)),
+    }
+}
+
+pub fn listen(addr: &str) -> Result<TcpListener> {
+    let (host, port) = with_port(addr)?;
+    TcpListener::bind((host.as_str(), port))
+        .map_err(|e| Error::Network(format!("cannot listen on {}: {}", addr, e)))
+}
+
+/// Addresses with a `/` in them are Unix socket paths.
+fn is_unix(addr: &str) -> bool {
+    addr.contains('/')
+}
+
+/// Listens on a TCP address or, if `addr` has a `/` in it, a Unix socket.
+pub fn bind(addr: &str) -> Result<Listener> {
+    let listener = match is_unix(addr) {
+        true => UnixListener::bind(addr).map(|listener| Listener::Unix(listener, addr.into())),
+        false => {
+            let (host, port) = with_port(addr)?;
+            TcpListener::bind((host.as_str(), port)).map(Listener::Tcp)
+        }
+    };
+    listener.map_err(|e| Error::Network(format!("cannot listen on {}: {}", addr, e)))
+}
+
+/// Connects to what `bind` listens on.
+pub fn connect(addr: &str) -> Result<Stream> {
+    let stream = match is_unix(addr) {
+        true => UnixStream::connect(addr).map(Stream::from),
+        false => {
+            let (host, port) = with_port(addr)?;
+            TcpStream::connect((host.as_str(), port)).map(Stream::from)
+        }
+    };
+    stream.map_err(|e| Error::Network(format!("cannot connect to {}: {}", addr, e)))
+}
+
+/// Waits for the other player and tells them which board to race on.
+pub fn accept(
+    listener: &TcpListener,
+    settings: &GameSettings,
+    opening: &Position,
+) -> Result<Connection> {
+    let network = |e: io::Error| Error::Network(format!("cannot start the race: {}", e));
+    let (stream, _) = listener.accept().map_err(network)?;
+    let mut connection = Connection::new(stream).map_err(network)?;
+
+    connection
+        .send(&Message::Start {
+            settings: *settings,
+            opening: opening.clone(),
+        })
+        .map_err(network)?;
+    connection.set_nonblocking().map_err(network)?;
+
+    Ok(connection)
+}
+
+/// Connects to a host and waits for the board to race on.
+pub fn join(addr: &str) -> Result<(Connection, GameSettings, Position)> {
+    let mut connection = Connection::new(connect(addr)?)?;
+
+    // Still blocking, so this waits for the first message.
+    match connection.poll()? {
+        Some(Message::Start { settings, opening }) => {
+            connection.set_nonblocking()?;
+            Ok((connection, settings, opening))
+        }
+        _ => Err(Error::Network(format!(
+            "{} did not send a board to race on",
+            addr
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::sweeper::Sweeper;
+
+    use super::*;
+
+    fn wait_for(connection: &mut Connection) -> Message {
+        loop {
+            if let Some(message) = connection.poll().unwrap() {
+                return message;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_race_over_localhost() {
+        let settings = GameSettings {
+            difficulty: Difficulty::Custom,
+            custom_params: Some(CustomParams::with_defaults(Some(12), Some(20), None)),
+            seed: Some(42),
+            no_guess: false,
+            daily: None,
+            lives: None,
+            time_limit: None,
+            rules: RuleSet::Classic,
+        };
+        let opening = Position(6, 10, 0);
+
+        let listener = listen("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let guest = thread::spawn(move || join(&addr).unwrap());
+        let mut host = accept(&listener, &settings, &opening).unwrap();
+        let (mut guest, guest_settings, guest_opening) = guest.join().unwrap();
+
+        // Both ends deal the same board.
+        let deal = |settings: &GameSettings, opening: &Position| {
+            let mut sweeper = Sweeper::from_settings(settings).unwrap();
+            sweeper.reveal(opening);
+            sweeper.field.to_string()
+        };
+        assert_eq!(
+            deal(&settings, &opening),
+            deal(&guest_settings, &guest_opening)
+        );
+
+        let progress = Progress {
+            percent: 42,
+            state: GameState::Playing,
+            secs: 7,
+        };
+        assert!(host.poll().unwrap().is_none());
+        // A malformed line is skipped rather than ending the race.
+        guest.stream.write_all(b"progress lots\n").unwrap();
+        guest.send(&Message::Progress(progress)).unwrap();
+        assert_eq!(wait_for(&mut host), Message::Progress(progress));
+
+        host.send(&Message::Bye).unwrap();
+        drop(host);
+        assert_eq!(wait_for(&mut guest), Message::Bye);
+        assert_eq!(wait_for(&mut guest), Message::Bye);
+        assert!(guest.poll().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_with_port() {
+        let parsed = |addr: &str| with_port(addr).unwrap();
+        assert_eq!(parsed("localhost"), ("localhost".to_string(), DEFAULT_PORT));
+        assert_eq!(parsed("example.org:99"), ("example.org".to_string(), 99));
+        assert_eq!(parsed("10.0.0.1"), ("10.0.0.1".to_string(), DEFAULT_PORT));
+        assert_eq!(parsed("::1"), ("::1".to_string(), DEFAULT_PORT));
+        assert_eq!(parsed("[::1]"), ("::1".to_string(), DEFAULT_PORT));
+        assert_eq!(parsed("[::1]:99"), ("::1".to_string(), 99));
+        assert!(with_port("localhost:http").is_err());
+    }
+}
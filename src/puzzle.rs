@@ -0,0 +1,159 @@
+//! Logic puzzles: partly revealed boards that can be finished without
+//! guessing, graded by the hardest solver rule they need.
+
+use std::fmt;
+
+use crate::field::Field;
+use crate::layout;
+use crate::solver::{self, Rule};
+use crate::sweeper::Position;
+
+/// Boards tried by `generate` before giving up.
+pub const GENERATE_ATTEMPTS: u64 = 1000;
+
+const BUNDLED: [(&str, &str); 6] = [
+    ("easy-1", include_str!("../puzzles/easy-1.txt")),
+    ("easy-2", include_str!("../puzzles/easy-2.txt")),
+    ("medium-1", include_str!("../puzzles/medium-1.txt")),
+    ("medium-2", include_str!("../puzzles/medium-2.txt")),
+    ("hard-1", include_str!("../puzzles/hard-1.txt")),
+    ("hard-2", include_str!("../puzzles/hard-2.txt")),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Grade {
+    /// Every step follows from a single number.
+    Easy,
+    /// Some step needs two numbers compared.
+    Medium,
+    /// Some step needs the number of mines left.
+    Hard,
+}
+
+impl Grade {
+    pub fn parse(grade: &str) -> Result<Self, String> {
+        match grade {
+            "easy" => Ok(Grade::Easy),
+            "medium" => Ok(Grade::Medium),
+            "hard" => Ok(Grade::Hard),
+            _ => Err(format!(
+                "unknown grade `{}`, expected easy, medium or hard",
+                grade
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Grade::Easy => "easy",
+            Grade::Medium => "medium",
+            Grade::Hard => "hard",
+        }
+    }
+
+    /// The grade of a puzzle that takes `rules` to solve.
+    pub fn of(rules: &[Rule]) -> Self {
+        match rules.iter().max() {
+            Some(Rule::GlobalCount) => Grade::Hard,
+            Some(Rule::Subset) => Grade::Medium,
+            Some(Rule::SingleCell) | None => Grade::Easy,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Puzzle {
+    pub name: String,
+    pub grade: Grade,
+    pub field: Field,
+}
+
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<12}{:<8}{}x{}, {} mines",
+            self.name,
+            self.grade.name(),
+            self.field.rows,
+            self.field.cols,
+            self.field.bombs()
+        )
+    }
+}
+
+impl Puzzle {
+    /// Fails unless `field` has tiles revealed, tiles left to reveal, and
+    /// can be finished without guessing.
+    pub fn new(name: &str, field: Field) -> Result<Self, String> {
+        let tiles = || field.tile_matrix.iter().flatten();
+        if !tiles().any(|tile| tile.revealed) || field.is_cleared() {
+            return Err(format!("{} has nothing to solve", name));
+        }
+        if tiles().any(|tile| tile.revealed && tile.is_bomb()) {
+            return Err(format!("{} has a bomb revealed", name));
+        }
+        if !field.rules.is_classic() {
+            return Err(format!(
+                "{} uses the {} rules, puzzles are classic",
+                name, field.rules
+            ));
+        }
+
+        let rules = solver::solve_revealed(&field)
+            .ok_or_else(|| format!("{} cannot be solved without guessing", name))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            grade: Grade::of(&rules),
+            field,
+        })
+    }
+}
+
+/// The puzzles that come with the game, easiest first.
+pub fn bundled() -> Vec<Puzzle> {
+    BUNDLED
+        .iter()
+        .map(|(name, contents)| {
+            let field = layout::parse(contents).expect("bundled puzzles are valid layouts");
+            Puzzle::new(name, field).expect("bundled puzzles can be solved")
+        })
+        .collect()
+}
+
+pub fn find(name: &str) -> Option<Puzzle> {
+    bundled().into_iter().find(|puzzle| puzzle.name == name)
+}
+
+/// Deals boards from `seed` on until the opening around the centre leaves a
+/// puzzle of `grade`.
+pub fn generate(rows: usize, cols: usize, bombs: usize, grade: Grade, seed: u64) -> Option<Puzzle> {
+    let start = Position(rows / 2, cols / 2, 0);
+    let field = Field::create_with_bombs(rows, cols, bombs).ok()?;
+
+    (0..GENERATE_ATTEMPTS)
+        .map(|attempt| {
+            let seed = seed.wrapping_add(attempt);
+            let field = field.with_seed(seed).populate(&start);
+            (seed, solver::opening(&field, &start))
+        })
+        .filter_map(|(seed, field)| Puzzle::new(&format!("seed {}", seed), field).ok())
+        .find(|puzzle| puzzle.grade == grade)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_puzzles() {
+        for puzzle in bundled() {
+            assert!(puzzle.name.starts_with(puzzle.grade.name()), "{}", puzzle);
+        }
+
+        let puzzle = generate(9, 9, 10, Grade::Medium, 1).unwrap();
+        assert_eq!(puzzle.grade, Grade::Medium);
+        assert!(solver::solve_revealed(&puzzle.field).is_some());
+    }
+}
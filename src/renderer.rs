@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use termion::{clear, cursor};
+
+use crate::terminal;
+
+/// A piece of a rendered line, `width` terminal columns wide once drawn.
+/// `content` may contain escape codes and must set its own colours.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub width: usize,
+    pub content: String,
+}
+
+impl Cell {
+    pub fn new(width: usize, content: String) -> Self {
+        Self { width, content }
+    }
+}
+
+/// One line of cells per terminal row.
+pub type Frame = Vec<Vec<Cell>>;
+
+/// Draws frames on the terminal, rewriting only the cells that changed since
+/// the previous frame instead of clearing the screen.
+#[derive(Debug, Default)]
+pub struct Renderer {
+    previous: Option<Frame>,
+}
+
+fn line_width(line: &[Cell]) -> usize {
+    line.iter().map(|cell| cell.width).sum()
+}
+
+fn goto(column: usize, line: usize) -> cursor::Goto {
+    cursor::Goto(column as u16 + 1, line as u16 + 1)
+}
+
+impl Renderer {
+    /// Forgets what is on screen, so the next frame is drawn from scratch.
+    /// Needed whenever something else may have drawn on the terminal.
+    pub fn invalidate(&mut self) {
+        self.previous = None;
+    }
+
+    pub fn draw<W: Write>(&mut self, stdout: &mut W, frame: Frame) -> io::Result<()> {
+        match self.previous.take() {
+            None => {
+                write!(stdout, "{}{}", clear::All, cursor::Hide)?;
+                for (y, line) in frame.iter().enumerate() {
+                    write!(stdout, "{}", goto(0, y))?;
+                    for cell in line {
+                        write!(stdout, "{}", cell.content)?;
+                    }
+                }
+            }
+            Some(previous) => {
+                for (y, line) in frame.iter().enumerate() {
+                    let previous_line = previous.get(y).map_or(&[][..], Vec::as_slice);
+                    self.draw_line_diff(stdout, y, previous_line, line)?;
+                }
+                for y in frame.len()..previous.len() {
+                    write!(stdout, "{}{}", goto(0, y), clear::CurrentLine)?;
+                }
+            }
+        }
+
+        self.previous = Some(frame);
+        Ok(())
+    }
+
+    /// Prints `frame` below whatever is on screen, without escape codes
+    /// beyond those in the cells, for terminals that cannot move the cursor
+    /// and for files.
+    pub fn draw_plain<W: Write>(stdout: &mut W, frame: Frame) -> io::Result<()> {
+        let newline = match terminal::is_raw() {
+            true => "\r\n",
+            false => "\n",
+        };
+        for line in frame {
+            let line: String = line.into_iter().map(|cell| cell.content).collect();
+            write!(stdout, "{}{}", line.trim_end(), newline)?;
+        }
+        Ok(())
+    }
+
+    fn draw_line_diff<W: Write>(
+        &self,
+        stdout: &mut W,
+        y: usize,
+        previous: &[Cell],
+        line: &[Cell],
+    ) -> io::Result<()> {
+        let mut x = 0;
+        let mut previous_x = 0;
+        let mut cursor_x = None;
+
+        for (index, cell) in line.iter().enumerate() {
+            let previous_cell = previous.get(index);
+            let unchanged = previous_cell == Some(cell) && previous_x == x;
+
+            if !unchanged {
+                if cursor_x != Some(x) {
+                    write!(stdout, "{}", goto(x, y))?;
+                }
+                write!(stdout, "{}", cell.content)?;
+                cursor_x = Some(x + cell.width);
+            }
+            x += cell.width;
+            previous_x += previous_cell.map_or(0, |cell| cell.width);
+        }
+
+        if line_width(previous) > x {
+            write!(stdout, "{}{}", goto(x, y), clear::UntilNewline)?;
+        }
+
+        Ok(())
+    }
+}
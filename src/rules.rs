@@ -0,0 +1,215 @@
+//! Rule sets: variants of the game that change what a tile can hold, what
+//! its number shows and when the board is won. The rule set of a board is
+//! saved with its layout, see `layout`.
+//!
+//! A rule set hooks into the game in three places: `max_mines` and `clue`
+//! when the board is dealt, `shown_number` when a number is drawn by
+//! `Tile::repr`, and `is_won` when the game checks for a win.
+
+use std::fmt;
+
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::field::Field;
+use crate::tile::Clue;
+
+/// Most mines a tile can hold in any rule set, so a count fits one digit.
+pub const MAX_MINES: u8 = 9;
+
+/// Mines per tile of `multi-mine` without a count.
+const DEFAULT_MULTI_MINES: u8 = 3;
+
+/// Share of the numbers left out by `hidden-numbers`.
+const HIDDEN_SHARE: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuleSet {
+    #[default]
+    Classic,
+    /// A tile holds up to this many mines, numbers add up the mines around
+    /// them and flags carry a count.
+    MultiMine(u8),
+    /// Every number is one more or one less than the mines around it, a 1
+    /// always shows 2.
+    /// Tiles without mines around them still open their neighbours.
+    Liar,
+    /// Some numbers are left out, their tiles only show that they are safe.
+    HiddenNumbers,
+}
+
+impl fmt::Display for RuleSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleSet::Classic => write!(f, "classic"),
+            RuleSet::MultiMine(mines) => write!(f, "multi-mine:{}", mines),
+            RuleSet::Liar => write!(f, "liar"),
+            RuleSet::HiddenNumbers => write!(f, "hidden-numbers"),
+        }
+    }
+}
+
+impl RuleSet {
+    pub const NAMES: [&'static str; 4] = ["classic", "multi-mine[:K]", "liar", "hidden-numbers"];
+
+    /// Parses one of `NAMES`, where `multi-mine:K` allows up to K mines per
+    /// tile.
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let (name, count) = match rules.split_once(':') {
+            Some((name, count)) => (name, Some(count)),
+            None => (rules, None),
+        };
+
+        match (name, count) {
+            ("classic", None) => Ok(RuleSet::Classic),
+            ("multi-mine", None) => Ok(RuleSet::MultiMine(DEFAULT_MULTI_MINES)),
+            ("multi-mine", Some(count)) => match count.parse() {
+                Ok(mines @ 2..=MAX_MINES) => Ok(RuleSet::MultiMine(mines)),
+                _ => Err(format!(
+                    "`{}` is not a number of mines from 2 to {}",
+                    count, MAX_MINES
+                )),
+            },
+            ("liar", None) => Ok(RuleSet::Liar),
+            ("hidden-numbers", None) => Ok(RuleSet::HiddenNumbers),
+            _ => Err(format!(
+                "unknown rules `{}`, expected one of: {}",
+                rules,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+
+    /// Most mines on one tile.
+    pub fn max_mines(&self) -> u8 {
+        match self {
+            RuleSet::MultiMine(mines) => *mines,
+            RuleSet::Classic | RuleSet::Liar | RuleSet::HiddenNumbers => 1,
+        }
+    }
+
+    /// What a tile with `number` mines around it shows, dealt along with
+    /// the mines.
+    pub fn clue(&self, number: u8, rng: &mut ChaCha8Rng) -> Clue {
+        match self {
+            RuleSet::Classic | RuleSet::MultiMine(_) => Clue::Number,
+            // A 1 lying downwards would look like an empty tile.
+            RuleSet::Liar => match number == 1 || rng.gen_bool(0.5) {
+                true => Clue::Lie(number + 1),
+                false => Clue::Lie(number - 1),
+            },
+            RuleSet::HiddenNumbers => match rng.gen_bool(HIDDEN_SHARE) {
+                true => Clue::Hidden,
+                false => Clue::Number,
+            },
+        }
+    }
+
+    /// What a revealed number dealt as `clue` shows for `mines` around it,
+    /// `None` when it is left out.
+    pub fn shown_number(clue: Clue, mines: u8) -> Option<u8> {
+        match clue {
+            Clue::Number => Some(mines),
+            Clue::Lie(shown) => Some(shown),
+            Clue::Hidden => None,
+        }
+    }
+
+    /// Whether the game on `field` is won. Every rule set so far asks for
+    /// all safe tiles to be revealed, whatever their numbers showed.
+    pub fn is_won(&self, field: &Field) -> bool {
+        match self {
+            RuleSet::Classic | RuleSet::MultiMine(_) | RuleSet::Liar | RuleSet::HiddenNumbers => {
+                field.is_cleared()
+            }
+        }
+    }
+
+    pub fn is_classic(&self) -> bool {
+        *self == RuleSet::Classic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout;
+    use crate::sweeper::Position;
+    use crate::tile::TileKind;
+
+    use super::*;
+
+    #[test]
+    fn test_rules_round_trip() {
+        for rules in [
+            RuleSet::Classic,
+            RuleSet::MultiMine(2),
+            RuleSet::MultiMine(9),
+            RuleSet::Liar,
+            RuleSet::HiddenNumbers,
+        ] {
+            assert_eq!(RuleSet::parse(&rules.to_string()), Ok(rules));
+        }
+        assert_eq!(RuleSet::parse("multi-mine"), Ok(RuleSet::MultiMine(3)));
+        assert!(RuleSet::parse("multi-mine:1").is_err());
+        assert!(RuleSet::parse("multi-mine:10").is_err());
+        assert!(RuleSet::parse("classic:2").is_err());
+        assert!(RuleSet::parse("liar:2").is_err());
+    }
+
+    #[test]
+    fn test_liar() {
+        let field = layout::parse("rules: liar\n*...*\n.....\n..*..\n").unwrap();
+        let numbers: Vec<(u8, Clue)> = field
+            .tile_matrix
+            .iter()
+            .flatten()
+            .filter_map(|tile| match tile.kind {
+                TileKind::Safe(number) => Some((number, tile.clue)),
+                _ => None,
+            })
+            .collect();
+
+        assert!(!numbers.is_empty());
+        for (number, clue) in numbers {
+            match clue {
+                Clue::Lie(shown) => {
+                    assert_eq!(shown.abs_diff(number), 1);
+                    assert_ne!(shown, 0);
+                }
+                clue => panic!("{:?} on a liar board", clue),
+            }
+        }
+        // Empty tiles tell the truth, they open their neighbours anyway.
+        assert_eq!(field.tile_matrix[0][2].kind, TileKind::Empty);
+        assert_eq!(field.tile_matrix[0][2].clue, Clue::Number);
+
+        // Lies are part of the deal, the same board always lies the same way.
+        let again = layout::parse(&layout::format(&field)).unwrap();
+        assert!(field
+            .tile_matrix
+            .iter()
+            .flatten()
+            .zip(again.tile_matrix.iter().flatten())
+            .all(|(tile, again)| tile.clue == again.clue));
+    }
+
+    #[test]
+    fn test_hidden_numbers() {
+        let field = Field::create_with_bombs(16, 16, 40)
+            .unwrap()
+            .with_seed(7)
+            .with_rules(RuleSet::HiddenNumbers)
+            .populate(&Position(8, 8, 0));
+        let numbers = || {
+            field
+                .tile_matrix
+                .iter()
+                .flatten()
+                .filter(|tile| tile.is_safe())
+        };
+        let hidden = numbers().filter(|tile| tile.clue == Clue::Hidden).count();
+
+        assert!(hidden > 0 && hidden < numbers().count() / 2);
+        assert!(numbers().all(|tile| matches!(tile.clue, Clue::Number | Clue::Hidden)));
+    }
+}
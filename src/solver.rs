@@ -0,0 +1,375 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::field::Field;
+use crate::sweeper::Position;
+use crate::tile::TileKind;
+
+/// Deduction rules, from the most local to the most global.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rule {
+    /// A number whose remaining mines equal zero or all its hidden neighbours.
+    SingleCell,
+    /// A number whose hidden neighbours contain those of another number.
+    Subset,
+    /// The total number of mines left on the board.
+    GlobalCount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Knowledge {
+    Hidden,
+    Mine,
+    /// Revealed, with this many bombs around it.
+    Revealed(u8),
+}
+
+/// Tiles proven safe or mined by a single rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deduction {
+    pub rule: Rule,
+    pub safe: Vec<Position>,
+    pub mines: Vec<Position>,
+}
+
+/// The hidden neighbours of a revealed number and how many of them are
+/// still unaccounted mines.
+struct Constraint {
+    unknown: BTreeSet<(usize, usize)>,
+    mines: usize,
+}
+
+/// Deduces tiles from what a player can see: revealed numbers and the
+/// total bomb count. Flags are ignored, the player may have got them wrong.
+#[derive(Debug, Clone)]
+pub struct Solver {
+    rows: usize,
+    cols: usize,
+    bombs: usize,
+    cells: Vec<Vec<Knowledge>>,
+}
+
+fn number(kind: TileKind) -> u8 {
+    match kind {
+        TileKind::Safe(bombs) => bombs,
+        _ => 0,
+    }
+}
+
+impl Solver {
+    pub fn from_field(field: &Field) -> Self {
+        Self {
+            rows: field.rows,
+            cols: field.cols,
+            bombs: field.bombs(),
+            cells: field
+                .tile_matrix
+                .iter()
+                .map(|tiles| {
+                    tiles
+                        .iter()
+                        .map(|tile| match tile.revealed && !tile.is_bomb() {
+                            true => Knowledge::Revealed(number(tile.kind)),
+                            false => Knowledge::Hidden,
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    fn neighbours(&self, (row, col): (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+        let (rows, cols) = (self.rows, self.cols);
+
+        (row.saturating_sub(1)..=row + 1)
+            .flat_map(move |r| (col.saturating_sub(1)..=col + 1).map(move |c| (r, c)))
+            .filter(move |&(r, c)| r < rows && c < cols && (r, c) != (row, col))
+    }
+
+    fn constraints(&self) -> HashMap<(usize, usize), Constraint> {
+        let mut constraints = HashMap::new();
+
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                if let Knowledge::Revealed(bombs) = self.cells[row][col] {
+                    let neighbours: Vec<(usize, usize)> = self.neighbours((row, col)).collect();
+                    let known_mines = neighbours
+                        .iter()
+                        .filter(|&&(r, c)| self.cells[r][c] == Knowledge::Mine)
+                        .count();
+                    let unknown: BTreeSet<(usize, usize)> = neighbours
+                        .into_iter()
+                        .filter(|&(r, c)| self.cells[r][c] == Knowledge::Hidden)
+                        .collect();
+
+                    if !unknown.is_empty() {
+                        constraints.insert(
+                            (row, col),
+                            Constraint {
+                                unknown,
+                                mines: (bombs as usize).saturating_sub(known_mines),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        constraints
+    }
+
+    fn single_cell(&self, constraints: &HashMap<(usize, usize), Constraint>) -> Deduction {
+        let mut safe = BTreeSet::new();
+        let mut mines = BTreeSet::new();
+
+        for constraint in constraints.values() {
+            if constraint.mines == 0 {
+                safe.extend(constraint.unknown.iter().copied());
+            } else if constraint.mines == constraint.unknown.len() {
+                mines.extend(constraint.unknown.iter().copied());
+            }
+        }
+
+        Self::deduction(Rule::SingleCell, safe, mines)
+    }
+
+    fn subset(&self, constraints: &HashMap<(usize, usize), Constraint>) -> Deduction {
+        let mut safe = BTreeSet::new();
+        let mut mines = BTreeSet::new();
+
+        for (&(row, col), inner) in constraints {
+            // Only numbers up to two tiles apart can share hidden neighbours.
+            let nearby = (row.saturating_sub(2)..=row + 2)
+                .flat_map(|r| (col.saturating_sub(2)..=col + 2).map(move |c| (r, c)))
+                .filter(|&position| position != (row, col));
+
+            for outer in nearby.filter_map(|position| constraints.get(&position)) {
+                if inner.unknown.len() >= outer.unknown.len()
+                    || !inner.unknown.is_subset(&outer.unknown)
+                    || outer.mines < inner.mines
+                {
+                    continue;
+                }
+
+                let rest = outer.unknown.difference(&inner.unknown).copied();
+                match outer.mines - inner.mines {
+                    0 => safe.extend(rest),
+                    mines_left if mines_left == outer.unknown.len() - inner.unknown.len() => {
+                        mines.extend(rest)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Self::deduction(Rule::Subset, safe, mines)
+    }
+
+    fn global_count(&self) -> Deduction {
+        let hidden: BTreeSet<(usize, usize)> = (0..self.rows)
+            .flat_map(|row| (0..self.cols).map(move |col| (row, col)))
+            .filter(|&(row, col)| self.cells[row][col] == Knowledge::Hidden)
+            .collect();
+        let known_mines = self
+            .cells
+            .iter()
+            .flatten()
+            .filter(|&&cell| cell == Knowledge::Mine)
+            .count();
+
+        match self.bombs.saturating_sub(known_mines) {
+            0 => Self::deduction(Rule::GlobalCount, hidden, BTreeSet::new()),
+            mines_left if mines_left == hidden.len() => {
+                Self::deduction(Rule::GlobalCount, BTreeSet::new(), hidden)
+            }
+            _ => Self::deduction(Rule::GlobalCount, BTreeSet::new(), BTreeSet::new()),
+        }
+    }
+
+    fn deduction(
+        rule: Rule,
+        safe: BTreeSet<(usize, usize)>,
+        mines: BTreeSet<(usize, usize)>,
+    ) -> Deduction {
+        Deduction {
+            rule,
+            safe: safe.into_iter().map(|(r, c)| Position(r, c, 0)).collect(),
+            mines: mines.into_iter().map(|(r, c)| Position(r, c, 0)).collect(),
+        }
+    }
+
+    /// Applies the rules in order and returns what the first productive one
+    /// proves, or `None` if the player would have to guess.
+    pub fn deduce(&self) -> Option<Deduction> {
+        let constraints = self.constraints();
+
+        [
+            self.single_cell(&constraints),
+            self.subset(&constraints),
+            self.global_count(),
+        ]
+        .into_iter()
+        .find(|deduction| !deduction.safe.is_empty() || !deduction.mines.is_empty())
+    }
+
+    /// Reveals `position` using the real `field`, flooding through empty tiles.
+    fn reveal(&mut self, field: &Field, position: &Position) {
+        let mut stack = vec![(position.0, position.1)];
+
+        while let Some((row, col)) = stack.pop() {
+            if self.cells[row][col] != Knowledge::Hidden {
+                continue;
+            }
+
+            let kind = field.tile_matrix[row][col].kind;
+            if let TileKind::Bomb(_) = kind {
+                continue;
+            }
+
+            self.cells[row][col] = Knowledge::Revealed(number(kind));
+            if kind == TileKind::Empty {
+                stack.extend(self.neighbours((row, col)));
+            }
+        }
+    }
+
+    fn is_solved(&self) -> bool {
+        self.cells
+            .iter()
+            .flatten()
+            .filter(|&&cell| cell != Knowledge::Hidden && cell != Knowledge::Mine)
+            .count()
+            == self.rows * self.cols - self.bombs
+    }
+
+    /// Marks the mines of `deduction` and reveals its safe tiles.
+    fn apply(&mut self, field: &Field, deduction: &Deduction) {
+        for Position(row, col, _) in &deduction.mines {
+            self.cells[*row][*col] = Knowledge::Mine;
+        }
+        for position in &deduction.safe {
+            self.reveal(field, position);
+        }
+    }
+
+    /// Deduces until every safe tile is revealed, returning the rules each
+    /// step needed, or `None` if a guess would be required.
+    fn finish(mut self, field: &Field) -> Option<Vec<Rule>> {
+        let mut rules = vec![];
+
+        while !self.is_solved() {
+            let deduction = self.deduce()?;
+            self.apply(field, &deduction);
+            rules.push(deduction.rule);
+        }
+
+        Some(rules)
+    }
+}
+
+/// Plays a populated `field` from `start` without ever guessing. Returns the
+/// rules each step needed, or `None` if a guess would have been required.
+pub fn solve(field: &Field, start: &Position) -> Option<Vec<Rule>> {
+    let mut solver = Solver::from_field(&field.game_start());
+    solver.reveal(field, start);
+    solver.finish(field)
+}
+
+/// Like `solve`, going on from the tiles already revealed on `field`.
+pub fn solve_revealed(field: &Field) -> Option<Vec<Rule>> {
+    Solver::from_field(field).finish(field)
+}
+
+/// Whether the mine at `position` could have been proven one from what was
+/// visible on `field`, revealing the tiles proven safe along the way. If not,
+/// stepping on it was a forced guess.
+pub fn is_deducible_mine(field: &Field, position: &Position) -> bool {
+    let mut solver = Solver::from_field(field);
+
+    while let Some(deduction) = solver.deduce() {
+        if deduction.mines.contains(position) {
+            return true;
+        }
+        solver.apply(field, &deduction);
+    }
+
+    false
+}
+
+/// `field` with `start` revealed, flooding through empty tiles.
+pub fn opening(field: &Field, start: &Position) -> Field {
+    let mut solver = Solver::from_field(&field.game_start());
+    solver.reveal(field, start);
+
+    let tiles = field
+        .game_start()
+        .tile_matrix
+        .into_iter()
+        .zip(&solver.cells)
+        .map(|(tiles, cells)| {
+            tiles
+                .into_iter()
+                .zip(cells)
+                .map(|(tile, cell)| match cell {
+                    Knowledge::Revealed(_) => tile.reveal(),
+                    _ => tile,
+                })
+                .collect()
+        })
+        .collect();
+
+    Field::from_tiles(tiles, field.bombs()).with_seed(field.seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout;
+
+    use super::*;
+
+    #[test]
+    fn test_solve() {
+        // The opening already reveals every safe tile.
+        let field = Field::from_bombs(3, 4, &[Position(0, 0, 0)]);
+        assert_eq!(solve(&field, &Position(2, 3, 0)), Some(vec![]));
+
+        let field = Field::from_bombs(2, 2, &[Position(0, 0, 0), Position(1, 1, 0)]);
+        assert_eq!(solve(&field, &Position(0, 1, 0)), None);
+
+        let field = Field::from_bombs(4, 4, &[Position(0, 1, 0), Position(3, 3, 0)]);
+        let rules = solve(&field, &Position(3, 0, 0)).unwrap();
+        assert!(!rules.is_empty());
+    }
+
+    #[test]
+    fn test_subset() {
+        // The 1-1 along the left edge proves the third tile safe, the 1-2
+        // along the right edge proves the second one a mine. No number is
+        // settled by itself.
+        let field = layout::parse(".*.*\noooo\n").unwrap();
+        let solver = Solver::from_field(&field);
+        let constraints = solver.constraints();
+        assert!(solver.single_cell(&constraints).safe.is_empty());
+        assert!(solver.single_cell(&constraints).mines.is_empty());
+
+        assert_eq!(
+            solver.deduce(),
+            Some(Deduction {
+                rule: Rule::Subset,
+                safe: vec![Position(0, 2, 0)],
+                mines: vec![Position(0, 1, 0)],
+            })
+        );
+        assert_eq!(solve_revealed(&field).unwrap()[0], Rule::Subset);
+    }
+
+    #[test]
+    fn test_is_deducible_mine() {
+        // The 1 in the corner leaves a single hidden neighbour.
+        let field = layout::parse("*o\noo\n").unwrap();
+        assert!(is_deducible_mine(&field, &Position(0, 0, 0)));
+
+        // Two hidden tiles next to a 1 on both sides: a coin toss.
+        let field = layout::parse("*.\noo\n").unwrap();
+        assert!(!is_deducible_mine(&field, &Position(0, 0, 0)));
+    }
+}
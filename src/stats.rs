@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::{self, Ini};
+use crate::daily::Date;
+use crate::error::{Error, Result};
+
+/// Results of the finished games on one difficulty or daily board.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Record {
+    pub played: u32,
+    pub won: u32,
+    /// Fastest win in seconds.
+    pub best: Option<u64>,
+}
+
+impl Record {
+    fn from_section(section: &HashMap<String, String>) -> std::result::Result<Self, String> {
+        let number = |key: &str| -> std::result::Result<Option<u64>, String> {
+            section
+                .get(key)
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a number: {}", key, value))
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            played: number("played")?.unwrap_or(0) as u32,
+            won: number("won")?.unwrap_or(0) as u32,
+            best: number("best")?,
+        })
+    }
+
+    fn to_section(&self) -> HashMap<String, String> {
+        let mut section = HashMap::from([
+            ("played".to_string(), self.played.to_string()),
+            ("won".to_string(), self.won.to_string()),
+        ]);
+        if let Some(best) = self.best {
+            section.insert("best".to_string(), best.to_string());
+        }
+        section
+    }
+}
+
+/// Per-difficulty and per-date records, kept in `$XDG_DATA_HOME/rust-sweeper/stats.ini`.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    path: Option<PathBuf>,
+    records: HashMap<String, Record>,
+}
+
+impl Stats {
+    /// Reads the stats file, starting from scratch if there is none.
+    pub fn load() -> Result<Self> {
+        let path = config::data_dir().map(|dir| dir.join("stats.ini"));
+        let ini = match &path {
+            Some(path) => config::read_ini(path)
+                .map_err(Error::Stats)?
+                .unwrap_or_default(),
+            None => Ini::new(),
+        };
+
+        let records = ini
+            .iter()
+            .map(|(name, section)| {
+                Record::from_section(section)
+                    .map(|record| (name.clone(), record))
+                    .map_err(|e| Error::Stats(format!("[{}] {}", name, e)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { path, records })
+    }
+
+    /// The record under `key`, see `GameSettings::stats_key`.
+    pub fn record(&self, key: &str) -> Record {
+        self.records.get(key).cloned().unwrap_or_default()
+    }
+
+    /// Counts a finished game, `elapsed` only matters for wins.
+    pub fn add_game(&mut self, key: &str, won: bool, elapsed: Duration) {
+        let record = self.records.entry(key.to_string()).or_default();
+
+        record.played += 1;
+        if won {
+            record.won += 1;
+            record.best = Some(
+                record
+                    .best
+                    .map_or(elapsed.as_secs(), |best| best.min(elapsed.as_secs())),
+            );
+        }
+    }
+
+    /// Counts a finished daily board unless `date` already has a result,
+    /// so a lost daily cannot be restarted into a win.
+    pub fn add_daily(&mut self, date: Date, won: bool, elapsed: Duration) {
+        let key = date.stats_key();
+        if self.record(&key).played == 0 {
+            self.add_game(&key, won, elapsed);
+        }
+    }
+
+    /// Consecutive days with a won daily board, up to `today`. A streak is
+    /// not broken before today's board has been won.
+    pub fn streak(&self, today: Date) -> u32 {
+        let won = |date: Date| self.record(&date.stats_key()).won > 0;
+        let mut date = match won(today) {
+            true => today,
+            false => today.previous(),
+        };
+
+        let mut streak = 0;
+        while won(date) {
+            streak += 1;
+            date = date.previous();
+        }
+        streak
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let ini: Ini = self
+            .records
+            .iter()
+            .map(|(name, record)| (name.clone(), record.to_section()))
+            .collect();
+
+        match &self.path {
+            Some(path) => config::write_ini(path, &ini).map_err(Error::Stats),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streak() {
+        let today = Date {
+            year: 2024,
+            month: 3,
+            day: 2,
+        };
+        let mut stats = Stats::default();
+        let second = Duration::from_secs(1);
+
+        stats.add_game(&today.previous().previous().stats_key(), true, second);
+        stats.add_game(&today.previous().stats_key(), false, second);
+        stats.add_game(&today.previous().stats_key(), true, second);
+        assert_eq!(stats.streak(today), 2);
+
+        stats.add_game(&today.stats_key(), true, second);
+        assert_eq!(stats.streak(today), 3);
+        assert_eq!(stats.record(&today.previous().stats_key()).played, 2);
+
+        assert_eq!(stats.streak(today.previous().previous().previous()), 0);
+    }
+
+    #[test]
+    fn test_daily_counts_once() {
+        let today = Date {
+            year: 2024,
+            month: 3,
+            day: 2,
+        };
+        let mut stats = Stats::default();
+        let second = Duration::from_secs(1);
+
+        stats.add_daily(today, false, second);
+        stats.add_daily(today, true, second);
+        assert_eq!(stats.record(&today.stats_key()).played, 1);
+        assert_eq!(stats.streak(today), 0);
+    }
+}
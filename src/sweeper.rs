@@ -1,27 +1,48 @@
-use std::io::Stdout;
-
-use termion;
 use termion::event::Key;
-use termion::raw::RawTerminal;
 
-use std::io::Write;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
 
+use crate::coop::Overlay;
+use crate::daily::{self, Date};
+use crate::error::{Error, Result};
 use crate::field::Field;
-use crate::tile::{Tile, TileKind};
+use crate::keymap::{Action, Keymap};
+use crate::renderer::{Cell, Renderer};
+use crate::rules::RuleSet;
+use crate::solver;
+use crate::theme::Theme;
+use crate::tile::{Clue, Tile, TileKind};
+use crate::viewport::Viewport;
 
+/// Row, column and layer, which may lie off the field.
 #[derive(Debug, Clone, PartialEq)]
-pub struct UnsafePosition(pub i32, pub i32);
+pub struct UnsafePosition(pub i32, pub i32, pub i32);
+#[allow(dead_code)]
 impl UnsafePosition {
-    pub fn to_safe(&self) -> Position {
-        Position(self.0.try_into().unwrap(), self.1.try_into().unwrap())
+    /// `None` if any coordinate is negative.
+    pub fn to_safe(&self) -> Option<Position> {
+        match (
+            usize::try_from(self.0),
+            usize::try_from(self.1),
+            usize::try_from(self.2),
+        ) {
+            (Ok(row), Ok(col), Ok(layer)) => Some(Position(row, col, layer)),
+            _ => None,
+        }
     }
 }
 
+/// Row, column and layer of a tile. Only layered boards have more than
+/// layer 0.
 #[derive(Debug, Clone, PartialEq)]
-pub struct Position(pub usize, pub usize);
+pub struct Position(pub usize, pub usize, pub usize);
 impl Position {
+    /// Coordinates past `i32::MAX` saturate, which still lies off any field
+    /// that fits in memory.
     pub fn to_unsafe(&self) -> UnsafePosition {
-        UnsafePosition(self.0.try_into().unwrap(), self.1.try_into().unwrap())
+        let saturate = |value: usize| i32::try_from(value).unwrap_or(i32::MAX);
+        UnsafePosition(saturate(self.0), saturate(self.1), saturate(self.2))
     }
 }
 
@@ -35,12 +56,299 @@ pub enum Difficulty {
     Custom,
 }
 
-#[allow(dead_code)]
-enum CursorDirection {
+impl Difficulty {
+    /// Parses a difficulty by number (0-3) or by name.
+    pub fn parse(difficulty: &str) -> Result<Self> {
+        match difficulty.to_lowercase().as_str() {
+            "0" | "easy" => Ok(Difficulty::Easy),
+            "1" | "medium" => Ok(Difficulty::Medium),
+            "2" | "hard" => Ok(Difficulty::Hard),
+            "3" | "nightmare" => Ok(Difficulty::Nightmare),
+            _ => Err(Error::UnknownDifficulty(difficulty.to_string())),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Nightmare => "nightmare",
+            Difficulty::Custom => "custom",
+        }
+    }
+
+    /// Rows, columns and bomb percentile of the preset boards.
+    pub fn board(&self) -> Option<(usize, usize, f32)> {
+        match self {
+            Difficulty::Easy => Some((9, 9, 0.125)),
+            Difficulty::Medium => Some((16, 16, 0.15625)),
+            Difficulty::Hard => Some((16, 30, 0.20625)),
+            Difficulty::Nightmare => Some((25, 55, 0.35)),
+            Difficulty::Custom => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bombs {
+    Percentile(f32),
+    Count(usize),
+}
+
+impl Bombs {
+    /// The number of bombs on a `rows` x `cols` board.
+    pub fn count(&self, rows: usize, cols: usize) -> usize {
+        match self {
+            Bombs::Percentile(bomb_percentile) => ((rows * cols) as f32 * bomb_percentile) as usize,
+            Bombs::Count(bombs) => *bombs,
+        }
+    }
+}
+
+/// Rows and columns of each layer of a layered board unless given.
+pub const LAYER_SIZE: usize = 8;
+
+/// Share of tiles holding a mine on a layered board unless given. Lower
+/// than on a flat board, a tile has three times the neighbours.
+pub const LAYER_DENSITY: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CustomParams {
+    /// Boards stacked on each other, see `Field::create_layered`.
+    pub layers: usize,
+    pub rows: usize,
+    pub cols: usize,
+    pub bombs: Bombs,
+}
+
+impl CustomParams {
+    /// Fills in whatever was not given: a missing side copies the other one
+    /// (or 16, like Medium), missing bombs use Medium's density.
+    pub fn with_defaults(rows: Option<usize>, cols: Option<usize>, bombs: Option<Bombs>) -> Self {
+        Self {
+            layers: 1,
+            rows: rows.or(cols).unwrap_or(16),
+            cols: cols.or(rows).unwrap_or(16),
+            bombs: bombs.unwrap_or(Bombs::Percentile(0.15625)),
+        }
+    }
+
+    /// A stack of `layers` boards, filled in like `with_defaults` but with
+    /// `LAYER_SIZE` and `LAYER_DENSITY`.
+    pub fn layered(
+        layers: usize,
+        rows: Option<usize>,
+        cols: Option<usize>,
+        bombs: Option<Bombs>,
+    ) -> Self {
+        Self {
+            layers,
+            rows: rows.or(cols).unwrap_or(LAYER_SIZE),
+            cols: cols.or(rows).unwrap_or(LAYER_SIZE),
+            bombs: bombs.unwrap_or(Bombs::Percentile(LAYER_DENSITY)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CursorDirection {
     Up,
     Down,
     Left,
     Right,
+    PreviousLayer,
+    NextLayer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CursorJump {
+    RowStart,
+    RowEnd,
+    Top,
+    Bottom,
+    NextUnrevealed,
+    NextNumber,
+}
+
+/// What a movement key does with the cursor: steps are repeated by a count,
+/// jumps are aimed by it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Step(CursorDirection),
+    Jump(CursorJump),
+}
+
+impl Motion {
+    pub fn from_action(action: Action) -> Option<Self> {
+        match action {
+            Action::MoveUp => Some(Motion::Step(CursorDirection::Up)),
+            Action::MoveDown => Some(Motion::Step(CursorDirection::Down)),
+            Action::MoveLeft => Some(Motion::Step(CursorDirection::Left)),
+            Action::MoveRight => Some(Motion::Step(CursorDirection::Right)),
+            Action::PreviousLayer => Some(Motion::Step(CursorDirection::PreviousLayer)),
+            Action::NextLayer => Some(Motion::Step(CursorDirection::NextLayer)),
+            Action::RowStart => Some(Motion::Jump(CursorJump::RowStart)),
+            Action::RowEnd => Some(Motion::Jump(CursorJump::RowEnd)),
+            Action::Top => Some(Motion::Jump(CursorJump::Top)),
+            Action::Bottom => Some(Motion::Jump(CursorJump::Bottom)),
+            Action::NextUnrevealed => Some(Motion::Jump(CursorJump::NextUnrevealed)),
+            Action::NextNumber => Some(Motion::Jump(CursorJump::NextNumber)),
+            _ => None,
+        }
+    }
+}
+
+/// Counts larger than this are clamped, nothing on a board needs more.
+const MAX_COUNT: usize = 9999;
+
+/// The digits typed before a motion, as in `5d` or `12G`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CountPrefix(Option<usize>);
+
+impl CountPrefix {
+    /// Adds `key` to the count if it is a digit bound to no `action`,
+    /// returning whether it was. A leading 0 is not a count.
+    pub fn push(&mut self, key: &Key, action: Option<Action>) -> bool {
+        match (action, key, self.0) {
+            (None, Key::Char('0'), None) => false,
+            (None, Key::Char(digit @ '0'..='9'), count) => {
+                let digit = digit.to_digit(10).unwrap_or(0) as usize;
+                self.0 = Some((count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The count typed so far, if any, starting a new one.
+    pub fn take(&mut self) -> Option<usize> {
+        self.0.take()
+    }
+}
+
+/// The first of `len` indices after `start` in order, wrapping around, that
+/// matches `predicate`, as the cursor searches for the next tile.
+pub fn find_after(
+    start: usize,
+    len: usize,
+    mut predicate: impl FnMut(usize) -> bool,
+) -> Option<usize> {
+    (1..len)
+        .map(|offset| (start + offset) % len)
+        .find(|&index| predicate(index))
+}
+
+/// Boards tried for a no-guess game before settling for a regular one.
+const NO_GUESS_ATTEMPTS: u64 = 200;
+
+/// A clock running down from the first reveal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeLimit {
+    /// The game is lost when the time runs out.
+    Countdown(Duration),
+    /// Clear as many boards as possible, each finished board is followed
+    /// by a fresh one on the same clock.
+    Attack(Duration),
+}
+
+impl TimeLimit {
+    pub fn duration(&self) -> Duration {
+        match self {
+            TimeLimit::Countdown(duration) | TimeLimit::Attack(duration) => *duration,
+        }
+    }
+}
+
+/// `m:ss`, rounded up so the clock only shows 0:00 once time is up.
+fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Everything needed to start a game, from the command line or the menu.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameSettings {
+    pub difficulty: Difficulty,
+    pub custom_params: Option<CustomParams>,
+    /// A random seed is picked when `None`.
+    pub seed: Option<u64>,
+    pub no_guess: bool,
+    /// The day's board, overriding everything else.
+    pub daily: Option<Date>,
+    /// A casual game with this many lives, see `Sweeper::with_lives`.
+    pub lives: Option<u32>,
+    pub time_limit: Option<TimeLimit>,
+    pub rules: RuleSet,
+}
+
+impl Default for GameSettings {
+    /// A medium board with a random seed.
+    fn default() -> Self {
+        Self {
+            difficulty: Difficulty::Medium,
+            custom_params: None,
+            seed: None,
+            no_guess: false,
+            daily: None,
+            lives: None,
+            time_limit: None,
+            rules: RuleSet::Classic,
+        }
+    }
+}
+
+impl GameSettings {
+    pub fn daily(date: Date) -> Self {
+        Self {
+            difficulty: daily::DIFFICULTY,
+            custom_params: None,
+            seed: Some(date.seed()),
+            no_guess: true,
+            daily: Some(date),
+            lives: None,
+            time_limit: None,
+            rules: RuleSet::Classic,
+        }
+    }
+
+    /// Where finished games are counted in `Stats`, casual games, timed ones
+    /// and layered boards are not. Other rule sets than the classic one are
+    /// counted apart.
+    pub fn stats_key(&self) -> Option<String> {
+        let layered = self.custom_params.is_some_and(|params| params.layers > 1);
+        match (self.lives, self.time_limit, self.daily) {
+            _ if layered => None,
+            (Some(_), _, _) | (_, Some(_), _) => None,
+            (None, _, Some(date)) => Some(date.stats_key()),
+            (None, _, None) => match self.rules {
+                RuleSet::Classic => Some(self.difficulty.name().to_string()),
+                rules => Some(format!("{} {}", self.difficulty.name(), rules)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameState {
+    Playing,
+    Won,
+    Lost,
+}
+
+/// How far a player got, as shared with the other player in a race.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Share of the safe tiles revealed.
+    pub percent: u8,
+    pub state: GameState,
+    pub secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Opponent {
+    Racing(Progress),
+    Left,
 }
 
 #[allow(dead_code)]
@@ -48,58 +356,354 @@ enum CursorDirection {
 pub struct Sweeper {
     difficulty: Difficulty,
     pub field: Field,
+    pub viewport: Viewport,
+    keymap: Keymap,
+    theme: Theme,
+    renderer: Renderer,
+    pending_count: CountPrefix,
+    no_guess: bool,
+    started_at: Option<Instant>,
+    finished_at: Option<Instant>,
+    opponent: Option<Opponent>,
+    overlay: Option<Overlay>,
+    /// Someone else's game, shown read-only.
+    watching: bool,
+    /// The puzzle being solved, see `with_puzzle`.
+    puzzle: Option<String>,
+    /// Flags placed on safe tiles so far, shown when the puzzle is solved.
+    wrong_flags: usize,
+    /// After a loss, whether the solver could have told the fatal tile was
+    /// a mine or stepping on it was a forced guess.
+    deducible_mine: Option<bool>,
+    /// Lives left in a casual game, see `with_lives`.
+    lives: Option<u32>,
+    explosions: u32,
+    time_limit: Option<TimeLimit>,
+    /// When the time runs out, set by the first reveal.
+    deadline: Option<Instant>,
+    timed_out: bool,
+    /// The seconds left when the clock was last drawn.
+    shown_secs: Option<u64>,
+    /// Boards cleared so far in a time attack.
+    boards_cleared: u32,
 }
 
 #[allow(dead_code)]
 impl Sweeper {
-    pub fn new(difficulty: Difficulty, custom_params: Option<(usize, usize, f32)>) -> Self {
-        use Difficulty::*;
+    pub fn new(difficulty: Difficulty, custom_params: Option<CustomParams>) -> Result<Self> {
+        let field = match difficulty.board() {
+            Some((rows, cols, bomb_percentile)) => Field::create(rows, cols, bomb_percentile)?,
+            None => {
+                let CustomParams {
+                    layers,
+                    rows,
+                    cols,
+                    bombs,
+                } = custom_params.ok_or(Error::MissingCustomParams)?;
+                match bombs {
+                    Bombs::Percentile(bomb_percentile) if layers == 1 => {
+                        Field::create(rows, cols, bomb_percentile)?
+                    }
+                    Bombs::Percentile(bomb_percentile)
+                        if !(bomb_percentile > 0.0 && bomb_percentile < 1.0) =>
+                    {
+                        return Err(Error::InvalidBombPercentile(bomb_percentile))
+                    }
+                    bombs => {
+                        Field::create_layered(layers, rows, cols, bombs.count(layers * rows, cols))?
+                    }
+                }
+            }
+        };
 
+        Ok(Self::from_field(difficulty, field))
+    }
+
+    /// A game on an existing `field`, e.g. a co-op board dealt by a server.
+    pub fn from_field(difficulty: Difficulty, field: Field) -> Self {
         Self {
             difficulty,
-            field: {
-                match difficulty {
-                    Custom => {
-                        assert!(
-                            custom_params.is_some(),
-                            "Please pass custom parameters if you want a custom difficulty"
-                        );
-
-                        let (rows, cols, bomb_percentile) = custom_params.unwrap();
-                        Field::create(rows, cols, bomb_percentile)
-                    }
-                    Easy => Field::create(9, 9, 0.125),
-                    Medium => Field::create(16, 16, 0.15625),
-                    Hard => Field::create(16, 30, 0.20625),
-                    Nightmare => Field::create(25, 55, 0.35),
-                }
-            },
+            viewport: Viewport::new(field.layers, field.rows, field.cols),
+            field,
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            renderer: Renderer::default(),
+            pending_count: CountPrefix::default(),
+            no_guess: false,
+            started_at: None,
+            finished_at: None,
+            opponent: None,
+            overlay: None,
+            watching: false,
+            puzzle: None,
+            wrong_flags: 0,
+            deducible_mine: None,
+            lives: None,
+            explosions: 0,
+            time_limit: None,
+            deadline: None,
+            timed_out: false,
+            shown_secs: None,
+            boards_cleared: 0,
         }
     }
 
-    fn move_cursor(&self, current_cursor: UnsafePosition, direction: CursorDirection) -> Position {
-        let mut new_cursor = match direction {
-            CursorDirection::Up => UnsafePosition(current_cursor.0 - 1, current_cursor.1),
-            CursorDirection::Down => UnsafePosition(current_cursor.0 + 1, current_cursor.1),
-            CursorDirection::Left => UnsafePosition(current_cursor.0, current_cursor.1 - 1),
-            CursorDirection::Right => UnsafePosition(current_cursor.0, current_cursor.1 + 1),
+    pub fn from_settings(settings: &GameSettings) -> Result<Self> {
+        let sweeper = Self::new(settings.difficulty, settings.custom_params)?
+            .with_no_guess(settings.no_guess)
+            .with_rules(settings.rules)
+            .with_lives(settings.lives)
+            .with_time_limit(settings.time_limit);
+        let mut sweeper = match settings.seed {
+            Some(seed) => sweeper.with_seed(seed),
+            None => sweeper,
         };
 
-        if new_cursor.0 < 0 {
-            new_cursor.0 = 0
+        if let Some(date) = settings.daily {
+            sweeper.reveal(&date.first_click(sweeper.field.rows, sweeper.field.cols));
+        }
+
+        Ok(sweeper)
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            field: self.field.with_seed(seed),
+            ..self
+        }
+    }
+
+    /// Plays the field as the puzzle `name`: every flag placed on a safe tile
+    /// counts against the player, which is only told once the puzzle ends.
+    pub fn with_puzzle(self, name: &str) -> Self {
+        Self {
+            puzzle: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_rules(self, rules: RuleSet) -> Self {
+        Self {
+            field: self.field.with_rules(rules),
+            ..self
+        }
+    }
+
+    /// Only deal boards that can be cleared without guessing, see `solver`.
+    /// The solver only knows flat boards under the classic rules, other
+    /// boards are dealt as they come.
+    pub fn with_no_guess(self, no_guess: bool) -> Self {
+        Self { no_guess, ..self }
+    }
+
+    /// A casual game: stepping on a mine flags it and costs one of `lives`,
+    /// only losing the last one ends the game.
+    pub fn with_lives(self, lives: Option<u32>) -> Self {
+        Self { lives, ..self }
+    }
+
+    pub fn with_time_limit(self, time_limit: Option<TimeLimit>) -> Self {
+        Self { time_limit, ..self }
+    }
+
+    /// The board after this one in a time attack: `next` on the same clock,
+    /// dealt from the following seed.
+    pub fn next_board(&self, next: Sweeper) -> Self {
+        Self {
+            deadline: self.deadline,
+            boards_cleared: self.boards_cleared + u32::from(self.state() == GameState::Won),
+            ..next.with_seed(self.field.seed.wrapping_add(1))
+        }
+    }
+
+    pub fn is_attack(&self) -> bool {
+        matches!(self.time_limit, Some(TimeLimit::Attack(_)))
+    }
+
+    /// Whether the game was lost to the clock.
+    pub fn is_timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    /// Time left on the clock, which only starts on the first reveal.
+    pub fn remaining(&self) -> Option<Duration> {
+        let limit = self.time_limit?.duration();
+        Some(match self.deadline {
+            Some(deadline) => {
+                deadline.saturating_duration_since(self.finished_at.unwrap_or_else(Instant::now))
+            }
+            None => limit,
+        })
+    }
+
+    /// Ends the game once the time is up. Returns whether the clock shows a
+    /// different time than when it was last drawn.
+    pub fn tick_clock(&mut self) -> bool {
+        let remaining = match self.remaining() {
+            Some(remaining) => remaining,
+            None => return false,
+        };
+        if remaining.is_zero() && self.state() == GameState::Playing {
+            self.timed_out = true;
+            self.finished_at = Some(Instant::now());
+            return true;
         }
-        if new_cursor.0 > (self.field.rows as i32) - 1 {
-            new_cursor.0 = (self.field.rows as i32) - 1
+
+        let secs = Some(remaining.as_secs());
+        let changed = secs != self.shown_secs;
+        self.shown_secs = secs;
+        changed
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn state(&self) -> GameState {
+        match (
+            self.timed_out,
+            self.field.is_exploded(),
+            self.field.rules.is_won(&self.field),
+        ) {
+            (true, _, _) | (false, true, _) => GameState::Lost,
+            (false, false, true) => GameState::Won,
+            (false, false, false) => GameState::Playing,
         }
+    }
+
+    pub fn progress(&self) -> Progress {
+        let safe = || {
+            self.field
+                .tile_matrix
+                .iter()
+                .flatten()
+                .filter(|tile| !tile.is_bomb())
+        };
+        let safe_tiles = safe().count();
+        let revealed = safe().filter(|tile| tile.revealed).count();
 
-        if new_cursor.1 < 0 {
-            new_cursor.1 = 0
+        Progress {
+            percent: (revealed * 100 / safe_tiles.max(1)) as u8,
+            state: self.state(),
+            secs: self.elapsed().as_secs(),
         }
-        if new_cursor.1 > (self.field.cols as i32) - 1 {
-            new_cursor.1 = (self.field.cols as i32) - 1
+    }
+
+    /// Shows how the other player of a race is doing below the field.
+    pub fn set_opponent(&mut self, opponent: Opponent) {
+        self.opponent = Some(opponent);
+    }
+
+    /// Draws the other players of a co-op game on top of the field.
+    pub fn set_overlay(&mut self, overlay: Overlay) {
+        self.overlay = Some(overlay);
+    }
+
+    /// Marks the game as someone else's, watched from another terminal.
+    pub fn set_watching(&mut self) {
+        self.watching = true;
+    }
+
+    /// Time since the first reveal, frozen once the game is over.
+    pub fn elapsed(&self) -> Duration {
+        match (self.started_at, self.finished_at) {
+            (Some(started_at), Some(finished_at)) => finished_at - started_at,
+            (Some(started_at), None) => started_at.elapsed(),
+            (None, _) => Duration::ZERO,
         }
+    }
 
-        new_cursor.to_safe()
+    pub fn with_keymap(self, keymap: Keymap) -> Self {
+        Self { keymap, ..self }
+    }
+
+    pub fn with_theme(self, theme: Theme) -> Self {
+        Self { theme, ..self }
+    }
+
+    /// Re-layouts the viewport for a terminal of `width` x `height` characters.
+    pub fn resize(&mut self, (width, height): (u16, u16)) {
+        self.viewport = self.viewport.resize(width, height);
+        self.renderer.invalidate();
+    }
+
+    /// Draws the whole field on the next `display_field`, e.g. after
+    /// resuming from a suspend.
+    pub fn redraw(&mut self) {
+        self.renderer.invalidate();
+    }
+
+    fn move_cursor(&self, current_cursor: UnsafePosition, direction: CursorDirection) -> Position {
+        let UnsafePosition(row, col, layer) = current_cursor;
+        let new_cursor = match direction {
+            CursorDirection::Up => UnsafePosition(row - 1, col, layer),
+            CursorDirection::Down => UnsafePosition(row + 1, col, layer),
+            CursorDirection::Left => UnsafePosition(row, col - 1, layer),
+            CursorDirection::Right => UnsafePosition(row, col + 1, layer),
+            CursorDirection::PreviousLayer => UnsafePosition(row, col, layer - 1),
+            CursorDirection::NextLayer => UnsafePosition(row, col, layer + 1),
+        };
+
+        let clamp = |value: i32, len: usize| value.clamp(0, len as i32 - 1) as usize;
+
+        Position(
+            clamp(new_cursor.0, self.field.rows),
+            clamp(new_cursor.1, self.field.cols),
+            clamp(new_cursor.2, self.field.layers),
+        )
+    }
+
+    fn jump_cursor(
+        &self,
+        current_cursor: &Position,
+        jump: CursorJump,
+        count: Option<usize>,
+    ) -> Position {
+        let Position(row, col, layer) = *current_cursor;
+        let last_row = self.field.rows - 1;
+        let last_col = self.field.cols - 1;
+
+        match jump {
+            CursorJump::RowStart => Position(row, 0, layer),
+            CursorJump::RowEnd => Position(row, last_col, layer),
+            CursorJump::Top => Position(count.map_or(0, |c| (c - 1).min(last_row)), col, layer),
+            CursorJump::Bottom => Position(
+                count.map_or(last_row, |c| (c - 1).min(last_row)),
+                col,
+                layer,
+            ),
+            CursorJump::NextUnrevealed => (0..count.unwrap_or(1))
+                .fold(current_cursor.clone(), |cursor, _| {
+                    self.find_next(&cursor, |tile| !tile.revealed && !tile.is_flagged())
+                }),
+            CursorJump::NextNumber => {
+                (0..count.unwrap_or(1)).fold(current_cursor.clone(), |cursor, _| {
+                    self.find_next(&cursor, |tile| {
+                        tile.revealed
+                            && tile.is_safe()
+                            && self
+                                .field
+                                .get_neighbours(tile.position.to_unsafe())
+                                .iter()
+                                .any(|t| !t.revealed && !t.is_flagged())
+                    })
+                })
+            }
+        }
+    }
+
+    /// The first tile after `from` in reading order, layer after layer and
+    /// wrapping around the field, that matches `predicate`, or `from` itself
+    /// if none does.
+    fn find_next(&self, from: &Position, predicate: impl Fn(&Tile) -> bool) -> Position {
+        let tiles = self.field.layers * self.field.rows * self.field.cols;
+        let start = (from.2 * self.field.rows + from.0) * self.field.cols + from.1;
+        let tile = |index: usize| {
+            &self.field.tile_matrix[index / self.field.cols][index % self.field.cols]
+        };
+
+        find_after(start, tiles, |index| predicate(tile(index)))
+            .map_or(from.clone(), |index| tile(index).position.clone())
     }
 
     fn reveal_recursively(
@@ -112,142 +716,336 @@ impl Sweeper {
             return;
         }
 
-        let tile = self.field.get_tile(position.to_unsafe()).unwrap();
+        let Some(tile) = self.field.get_tile(position.to_unsafe()) else {
+            return;
+        };
         let neighbours = self.field.get_neighbours(position.to_unsafe());
         let safe_neighbours = neighbours
             .iter()
-            .filter(|t| !t.is_bomb() && !t.flagged && !t.revealed);
-        let flagged_neighbours = neighbours
-            .iter()
-            .filter(|t| t.flagged)
-            .collect::<Vec<&Tile>>();
+            .filter(|t| !t.is_bomb() && !t.is_flagged() && !t.revealed);
+        let flagged_neighbours: u8 = neighbours.iter().map(Tile::flags).sum();
 
-        self.field = self.field.reveal(&position);
+        self.field = self.field.reveal(position);
 
         match tile.revealed {
             true => match tile.kind {
                 TileKind::Empty => safe_neighbours
                     .for_each(|t| self.reveal_recursively(&t.position, false, _max_depth - 1)),
                 TileKind::Safe(bomb_count) => {
-                    match flagged_neighbours.len() == bomb_count.try_into().unwrap() {
-                        true => neighbours
-                            .clone()
+                    if flagged_neighbours == bomb_count && tile.clue == Clue::Number {
+                        neighbours
                             .iter()
-                            .filter(|t| !t.flagged)
-                            .for_each(|t| self.field = self.field.reveal(&t.position)),
-                        false => {}
+                            .filter(|t| !t.is_flagged())
+                            .for_each(|t| self.field = self.field.reveal(&t.position))
                     }
                 }
-                TileKind::Bomb => self.field = self.field.game_over(),
+                TileKind::Bomb(_) => {}
             },
             false => match is_revealing_after_populating {
                 true => safe_neighbours
                     .for_each(|t| self.reveal_recursively(&t.position, false, _max_depth - 1)),
                 false => match tile.kind {
                     TileKind::Safe(bomb_count) => {
-                        match flagged_neighbours.len() == bomb_count.try_into().unwrap() {
-                            true => neighbours
-                                .clone()
+                        if flagged_neighbours == bomb_count && tile.clue == Clue::Number {
+                            neighbours
                                 .iter()
-                                .filter(|t| !t.flagged)
-                                .for_each(|t| self.reveal_recursively(&t.position, false, 1)),
-                            false => {}
+                                .filter(|t| !t.is_flagged())
+                                .for_each(|t| self.reveal_recursively(&t.position, false, 1))
                         }
                     }
                     TileKind::Empty => safe_neighbours
                         .for_each(|t| self.reveal_recursively(&t.position, false, _max_depth - 1)),
-                    TileKind::Bomb => self.field = self.field.game_over(),
+                    TileKind::Bomb(_) => {}
                 },
             },
         }
     }
 
-    pub fn display_field(&self, stdout: &mut RawTerminal<Stdout>) {
-        stdout.suspend_raw_mode().unwrap();
-        writeln!(
-            stdout,
-            "{}{}{}{}",
-            termion::clear::All,
-            self.field,
-            termion::cursor::Goto(1, 1),
-            termion::cursor::Hide,
+    /// Populates the field around the first click. In no-guess mode, seeds
+    /// derived from the field's are tried until the solver can clear the
+    /// board, falling back to the original seed if none of them work.
+    fn populate(&self, starting_point: &Position) -> Field {
+        let seed = self.field.seed;
+        let no_guess_field = match self.no_guess && self.field.is_classic() {
+            true => (0..NO_GUESS_ATTEMPTS)
+                .map(|attempt| {
+                    self.field
+                        .with_seed(seed.wrapping_add(attempt))
+                        .populate(starting_point)
+                })
+                .find(|field| solver::solve(field, starting_point).is_some()),
+            false => None,
+        };
+
+        no_guess_field
+            .unwrap_or_else(|| self.field.populate(starting_point))
+            .with_seed(seed)
+    }
+
+    /// Reveals `position`, populating the field first if this is the
+    /// first click. Flagged tiles stay hidden. Stepping on a bomb reveals
+    /// the whole field.
+    pub fn reveal(&mut self, position: &Position) {
+        if self.field.tile(position).is_flagged() {
+            return;
+        }
+
+        let are_all_fields_empty = self
+            .field
+            .tile_matrix
+            .clone()
+            .iter()
+            .flatten()
+            .all(|tile| tile.is_empty());
+
+        if are_all_fields_empty {
+            self.field = self.populate(position);
+        }
+        // Fields dealt in advance, e.g. from a layout, are already populated.
+        if self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+        }
+        if let (None, Some(time_limit)) = (self.deadline, self.time_limit) {
+            self.deadline = Some(Instant::now() + time_limit.duration());
+        }
+        let before = self.field.clone();
+        self.reveal_recursively(position, are_all_fields_empty, 5);
+
+        // A chord can step on several mines at once.
+        while let (false, Some(fatal)) = (before.is_exploded(), self.field.exploded_position()) {
+            self.explosions += 1;
+            match self.lives {
+                Some(lives) if lives > 1 => {
+                    self.lives = Some(lives - 1);
+                    self.field = self.field.defuse(&fatal);
+                }
+                _ => {
+                    self.lives = self.lives.map(|_| 0);
+                    self.deducible_mine = match self.field.is_classic() {
+                        true => Some(solver::is_deducible_mine(&before, &fatal)),
+                        false => None,
+                    };
+                    self.field = self.field.game_over(&fatal);
+                    break;
+                }
+            }
+        }
+        if self.state() != GameState::Playing {
+            self.finished_at = Some(Instant::now());
+        }
+    }
+
+    fn status_line(&self) -> Cell {
+        let flags = self
+            .field
+            .tile_matrix
+            .iter()
+            .flatten()
+            .map(|tile| tile.flags() as usize)
+            .sum::<usize>();
+        let hint = match (self.opponent, &self.overlay, self.watching) {
+            (Some(_), _, _) | (_, Some(_), _) | (_, _, true) => {
+                format!("{}: quit", self.keymap.key_hint(Action::Quit))
+            }
+            (None, None, false) => format!(
+                "{}: new game, {}: quit",
+                self.keymap.key_hint(Action::Restart),
+                self.keymap.key_hint(Action::Quit)
+            ),
+        };
+
+        let status = match self.state() {
+            GameState::Playing => match self.field.layers {
+                1 => format!(
+                    "{}/{} flagged  seed {}",
+                    flags,
+                    self.field.bombs(),
+                    self.field.seed
+                ),
+                _ => format!(
+                    "{}/{} flagged  seed {}  {}/{}: switch layers",
+                    flags,
+                    self.field.bombs(),
+                    self.field.seed,
+                    self.keymap.key_hint(Action::PreviousLayer),
+                    self.keymap.key_hint(Action::NextLayer)
+                ),
+            },
+            GameState::Won => format!("Cleared in {}s! {}", self.elapsed().as_secs(), hint),
+            GameState::Lost => match self.deducible_mine {
+                Some(true) => format!("Boom! That mine could have been deduced. {}", hint),
+                Some(false) => format!("Boom! That was a forced guess. {}", hint),
+                None => format!("Boom! {}", hint),
+            },
+        };
+        let status = match (self.lives, self.state()) {
+            (None, _) | (Some(_), GameState::Lost) => status,
+            (Some(1), GameState::Playing) => {
+                format!("{}  1 life left, {} exploded", status, self.explosions)
+            }
+            (Some(lives), GameState::Playing) => format!(
+                "{}  {} lives left, {} exploded",
+                status, lives, self.explosions
+            ),
+            (Some(_), GameState::Won) => format!(
+                "Cleared in {}s, {} exploded! {}",
+                self.elapsed().as_secs(),
+                self.explosions,
+                hint
+            ),
+        };
+        let status = match (self.time_limit, self.state(), self.timed_out) {
+            (None, _, _) => status,
+            (Some(TimeLimit::Countdown(_)), GameState::Playing, _) => {
+                format!(
+                    "{}  {} left",
+                    status,
+                    clock(self.remaining().unwrap_or_default())
+                )
+            }
+            (Some(TimeLimit::Attack(_)), GameState::Playing, _) => format!(
+                "{}  {} cleared, {} left",
+                status,
+                self.boards_cleared,
+                clock(self.remaining().unwrap_or_default())
+            ),
+            (Some(TimeLimit::Countdown(_)), _, true) => format!("Time's up! {}", hint),
+            (Some(TimeLimit::Attack(_)), _, true) => {
+                format!(
+                    "Time's up! {} boards cleared. {}",
+                    self.boards_cleared, hint
+                )
+            }
+            (Some(_), _, false) => status,
+        };
+        let status = match (&self.overlay, self.state()) {
+            (None, _) => status,
+            (Some(overlay), GameState::Playing) => format!(
+                "{}/{} flagged  co-op: {} playing",
+                flags,
+                self.field.bombs(),
+                overlay.cursors.len()
+            ),
+            (Some(_), GameState::Won) => format!("Cleared together! {}", hint),
+            (Some(_), GameState::Lost) => format!("Boom! {}", hint),
+        };
+        let status = match (self.watching, self.state()) {
+            (false, _) => status,
+            (true, GameState::Playing) => format!(
+                "{}/{} flagged  watching, {}",
+                flags,
+                self.field.bombs(),
+                hint
+            ),
+            (true, GameState::Won) => format!("Cleared! {}", hint),
+            (true, GameState::Lost) => format!("Boom! {}", hint),
+        };
+        let status = match (&self.puzzle, self.state(), self.wrong_flags) {
+            (None, _, _) => status,
+            (Some(name), GameState::Playing, _) => {
+                format!("{}/{} flagged  puzzle {}", flags, self.field.bombs(), name)
+            }
+            (Some(_), GameState::Won, 0) => format!("Solved without a wrong flag! {}", hint),
+            (Some(_), GameState::Won, wrong_flags) => {
+                format!("Solved with {} wrong flags. {}", wrong_flags, hint)
+            }
+            (Some(_), GameState::Lost, _) => format!("Boom, that was a guess. {}", hint),
+        };
+        let status = match self.opponent {
+            None => status,
+            Some(Opponent::Left) => format!("{}  | opponent left", status),
+            Some(Opponent::Racing(progress)) => match progress.state {
+                GameState::Playing => format!("{}  | opponent {}%", status, progress.percent),
+                GameState::Won => format!("{}  | opponent cleared in {}s", status, progress.secs),
+                GameState::Lost => {
+                    format!("{}  | opponent blew up at {}%", status, progress.percent)
+                }
+            },
+        };
+
+        Cell::new(
+            status.chars().count() + 1,
+            format!("{} {}", self.theme.text(), status),
         )
-        .unwrap();
-        stdout.activate_raw_mode().unwrap();
+    }
+
+    pub fn display_field<W: Write>(&mut self, stdout: &mut W) -> io::Result<()> {
+        let mut frame = match &self.overlay {
+            Some(overlay) => self
+                .field
+                .render_with(&self.viewport, &self.theme, &|tile| {
+                    overlay.render_tile(tile, &self.theme)
+                }),
+            None => self.field.render(&self.viewport, &self.theme),
+        };
+        frame.push(vec![self.status_line()]);
+
+        match self.theme.is_plain() {
+            true => Renderer::draw_plain(stdout, frame),
+            false => self.renderer.draw(stdout, frame),
+        }
     }
 
     pub fn tick(&mut self, key: &Key, mut sweeper_cursor: Position) -> (bool, bool, Position) {
-        let unsafe_sweeper_cursor: UnsafePosition = sweeper_cursor.to_unsafe();
-
-        sweeper_cursor = match key {
-            Key::Char('w') => self.move_cursor(unsafe_sweeper_cursor, CursorDirection::Up),
-            Key::Char('s') => self.move_cursor(unsafe_sweeper_cursor, CursorDirection::Down),
-            Key::Char('a') => self.move_cursor(unsafe_sweeper_cursor, CursorDirection::Left),
-            Key::Char('d') => self.move_cursor(unsafe_sweeper_cursor, CursorDirection::Right),
-            Key::Char('i') => self.move_cursor(unsafe_sweeper_cursor, CursorDirection::Up),
-            Key::Char('k') => self.move_cursor(unsafe_sweeper_cursor, CursorDirection::Down),
-            Key::Char('j') => self.move_cursor(unsafe_sweeper_cursor, CursorDirection::Left),
-            Key::Char('l') => self.move_cursor(unsafe_sweeper_cursor, CursorDirection::Right),
-            _ => sweeper_cursor,
+        let action = self.keymap.action(key);
+
+        if self.pending_count.push(key, action) {
+            return (false, false, sweeper_cursor);
+        }
+
+        let count = self.pending_count.take();
+        sweeper_cursor = match action.and_then(Motion::from_action) {
+            Some(Motion::Step(direction)) => (0..count.unwrap_or(1))
+                .fold(sweeper_cursor, |cursor, _| {
+                    self.move_cursor(cursor.to_unsafe(), direction)
+                }),
+            Some(Motion::Jump(jump)) => self.jump_cursor(&sweeper_cursor, jump, count),
+            None => sweeper_cursor,
         };
 
         self.select(&sweeper_cursor);
 
-        match key {
-            Key::Char('f') => {
-                self.field = self.field.toggle_flag(&sweeper_cursor);
-                ()
-            }
-            Key::Char(' ') | Key::Char('e') => {
-                let are_all_fields_empty = self
-                    .field
-                    .tile_matrix
-                    .clone()
-                    .iter()
-                    .flatten()
-                    .all(|tile| tile.is_empty());
-
-                if are_all_fields_empty {
-                    self.field = self.field.populate(&sweeper_cursor);
+        match (self.state(), action) {
+            (GameState::Playing, Some(Action::Flag)) => {
+                let before = self.field.tile(&sweeper_cursor).clone();
+                self.field = self.field.cycle_mark(&sweeper_cursor);
+                let placed = !before.is_flagged() && self.field.tile(&sweeper_cursor).is_flagged();
+                if self.puzzle.is_some() && placed && !before.is_bomb() {
+                    self.wrong_flags += 1;
                 }
-                self.reveal_recursively(&sweeper_cursor, are_all_fields_empty, 5);
             }
+            (GameState::Playing, Some(Action::Reveal)) => self.reveal(&sweeper_cursor),
             _ => {}
         };
 
-        let should_exit = match key {
-            Key::Char('q') | Key::Ctrl('c') => true,
-            _ => false,
-        };
+        let should_exit = action == Some(Action::Quit);
 
-        let should_restart = match key {
-            Key::Char('r') => true,
-            _ => false,
-        };
+        // A race is played on one board, there is no new game to start.
+        let should_restart = action == Some(Action::Restart) && self.opponent.is_none();
 
         (should_exit, should_restart, sweeper_cursor)
     }
 
     pub fn select(&mut self, position: &Position) {
         self.field = self.field.select(position.clone());
+        self.viewport = self.viewport.follow(position);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::stdout;
-
-    use termion::raw::IntoRawMode;
+    use crate::theme::Theme;
+    use crate::tile::Mark;
 
     use super::*;
 
     #[test]
     fn test_reveal_recursively() {
-        let mut stdout = stdout().into_raw_mode().unwrap();
+        let mut stdout = Vec::new();
 
-        let mut sweeper = Sweeper::new(Difficulty::Easy, None);
+        let mut sweeper = Sweeper::new(Difficulty::Easy, None).unwrap();
 
-        let sweeper_cursor = Position(3, 3);
+        let sweeper_cursor = Position(3, 3, 0);
 
         let are_all_fields_empty = sweeper
             .field
@@ -262,6 +1060,337 @@ mod tests {
         }
         sweeper.reveal_recursively(&sweeper_cursor, are_all_fields_empty, 5);
 
-        sweeper.display_field(&mut stdout)
+        sweeper.display_field(&mut stdout).unwrap();
+
+        let before = snapshot(&mut sweeper);
+        sweeper.reveal_recursively(&Position(usize::MAX, 0, 0), false, 5);
+        assert_eq!(snapshot(&mut sweeper), before);
+    }
+
+    #[test]
+    fn test_count_prefixes_and_jumps() {
+        let mut sweeper = Sweeper::new(Difficulty::Easy, None).unwrap();
+        let mut cursor = Position(4, 4, 0);
+
+        for (key, expected) in [
+            ('3', Position(4, 4, 0)),
+            ('d', Position(4, 7, 0)),
+            ('$', Position(4, 8, 0)),
+            ('g', Position(0, 8, 0)),
+            ('5', Position(0, 8, 0)),
+            ('G', Position(4, 8, 0)),
+            ('n', Position(5, 0, 0)),
+            ('1', Position(5, 0, 0)),
+            ('2', Position(5, 0, 0)),
+            ('a', Position(5, 0, 0)),
+            ('^', Position(5, 0, 0)),
+        ] {
+            cursor = sweeper.tick(&Key::Char(key), cursor).2;
+            assert_eq!(cursor, expected, "after `{}`", key);
+        }
+    }
+
+    #[test]
+    fn test_marks_and_chording() {
+        let field = Field::from_bombs(3, 3, &[Position(0, 0, 0)]).reveal(&Position(1, 1, 0));
+        let mut sweeper = Sweeper::from_field(Difficulty::Custom, field);
+        let mark = |sweeper: &mut Sweeper, presses: usize| {
+            for _ in 0..presses {
+                sweeper.tick(&Key::Char('f'), Position(0, 0, 0));
+            }
+            sweeper.field.tile_matrix[0][0].mark
+        };
+
+        // A question mark is not a flag, so the 1 cannot be chorded yet.
+        assert_eq!(mark(&mut sweeper, 2), Mark::Question);
+        sweeper.reveal(&Position(1, 1, 0));
+        assert!(!sweeper.field.tile_matrix[0][1].revealed);
+
+        assert_eq!(mark(&mut sweeper, 2), Mark::Flag(1));
+        sweeper.reveal(&Position(1, 1, 0));
+        assert_eq!(sweeper.state(), GameState::Won);
+    }
+
+    fn snapshot(sweeper: &mut Sweeper) -> String {
+        let mut stdout = Vec::new();
+        sweeper.display_field(&mut stdout).unwrap();
+        String::from_utf8(stdout).unwrap()
+    }
+
+    #[test]
+    fn test_loss_analysis() {
+        let field = Field::from_bombs(2, 3, &[Position(0, 0, 0), Position(1, 2, 0)])
+            .reveal(&Position(1, 0, 0))
+            .reveal(&Position(1, 1, 0));
+        let mut sweeper = Sweeper::from_field(Difficulty::Custom, field).with_theme(Theme::ascii());
+
+        // The 1 and the 2 cannot tell the hidden tiles apart.
+        sweeper.tick(&Key::Char('f'), Position(0, 1, 0));
+        sweeper.tick(&Key::Char('f'), Position(1, 2, 0));
+        sweeper.reveal(&Position(0, 0, 0));
+        assert_eq!(sweeper.state(), GameState::Lost);
+
+        let screen = snapshot(&mut sweeper);
+        assert!(screen.contains("  @  x  1\n  1  2 [F]\n"));
+        assert!(screen.contains("Boom! That was a forced guess."));
+    }
+
+    #[test]
+    fn test_multi_mine() {
+        let field = crate::layout::parse("rules: multi-mine:3\n3.\noo\n").unwrap();
+        let mut sweeper = Sweeper::from_field(Difficulty::Custom, field).with_theme(Theme::ascii());
+        assert_eq!(sweeper.field.tile_matrix[1][0].kind, TileKind::Safe(3));
+
+        // One flag is not enough to chord a 3.
+        sweeper.tick(&Key::Char('f'), Position(0, 0, 0));
+        sweeper.reveal(&Position(1, 0, 0));
+        assert!(!sweeper.field.tile_matrix[0][1].revealed);
+
+        sweeper.tick(&Key::Char('f'), Position(0, 0, 0));
+        sweeper.tick(&Key::Char('f'), Position(0, 0, 0));
+        assert_eq!(sweeper.field.tile_matrix[0][0].mark, Mark::Flag(3));
+        let screen = snapshot(&mut sweeper);
+        assert!(screen.contains(" [F3 #\n  3  3\n"), "{}", screen);
+        assert!(screen.contains("3/3 flagged"));
+
+        sweeper.reveal(&Position(1, 0, 0));
+        assert_eq!(sweeper.state(), GameState::Won);
+        sweeper.tick(&Key::Char('f'), Position(0, 0, 0));
+        assert_eq!(sweeper.field.tile_matrix[0][0].mark, Mark::Flag(3));
+    }
+
+    #[test]
+    fn test_puzzle_scores_wrong_flags_at_the_end() {
+        let field = crate::layout::parse("*.\noo\n").unwrap();
+        let mut sweeper = Sweeper::from_field(Difficulty::Custom, field)
+            .with_theme(Theme::ascii())
+            .with_puzzle("test");
+
+        // The flag on the safe tile is placed like any other.
+        sweeper.tick(&Key::Char('f'), Position(0, 1, 0));
+        assert!(sweeper.field.tile_matrix[0][1].is_flagged());
+        let screen = snapshot(&mut sweeper);
+        assert!(!screen.contains("wrong"), "{}", screen);
+
+        sweeper.tick(&Key::Char('f'), Position(0, 1, 0));
+        sweeper.tick(&Key::Char('f'), Position(0, 1, 0));
+        sweeper.tick(&Key::Char(' '), Position(0, 1, 0));
+        assert_eq!(sweeper.state(), GameState::Won);
+        let screen = snapshot(&mut sweeper);
+        assert!(screen.contains("Solved with 1 wrong flags"), "{}", screen);
+    }
+
+    #[test]
+    fn test_lives() {
+        let field = Field::from_bombs(2, 3, &[Position(0, 0, 0), Position(0, 2, 0)]);
+        let mut sweeper = Sweeper::from_field(Difficulty::Custom, field).with_lives(Some(2));
+
+        sweeper.reveal(&Position(0, 0, 0));
+        assert_eq!(sweeper.state(), GameState::Playing);
+        assert_eq!((sweeper.lives, sweeper.explosions), (Some(1), 1));
+        let tile = &sweeper.field.tile_matrix[0][0];
+        assert!(tile.exploded && tile.is_flagged() && !tile.revealed);
+
+        sweeper.reveal(&Position(0, 2, 0));
+        assert_eq!(sweeper.state(), GameState::Lost);
+
+        let settings = GameSettings {
+            lives: Some(3),
+            ..GameSettings::default()
+        };
+        assert_eq!(settings.stats_key(), None);
+    }
+
+    #[test]
+    fn test_time_limits() {
+        assert_eq!(clock(Duration::from_millis(60_500)), "1:01");
+        assert_eq!(clock(Duration::ZERO), "0:00");
+
+        let field = || Field::from_bombs(2, 3, &[Position(0, 0, 0)]);
+        let limit = Duration::from_millis(20);
+        let mut sweeper = Sweeper::from_field(Difficulty::Custom, field())
+            .with_time_limit(Some(TimeLimit::Countdown(limit)));
+
+        // The clock only starts on the first reveal.
+        std::thread::sleep(limit);
+        sweeper.tick_clock();
+        assert_eq!(sweeper.state(), GameState::Playing);
+        sweeper.reveal(&Position(1, 0, 0));
+        std::thread::sleep(limit);
+        assert!(sweeper.tick_clock());
+        assert_eq!(sweeper.state(), GameState::Lost);
+
+        let mut sweeper = Sweeper::from_field(Difficulty::Custom, field())
+            .with_time_limit(Some(TimeLimit::Attack(Duration::from_secs(60))));
+        sweeper.reveal(&Position(0, 2, 0));
+        sweeper.reveal(&Position(1, 0, 0));
+        assert_eq!(sweeper.state(), GameState::Won);
+        let next = sweeper.next_board(Sweeper::from_field(Difficulty::Custom, field()));
+        assert_eq!((next.boards_cleared, next.deadline), (1, sweeper.deadline));
+        assert_eq!(next.field.seed, sweeper.field.seed + 1);
+    }
+
+    #[test]
+    fn test_ascii_snapshots() {
+        let mut sweeper = Sweeper::new(
+            Difficulty::Custom,
+            Some(CustomParams::with_defaults(Some(6), Some(8), None)),
+        )
+        .unwrap()
+        .with_theme(Theme::ascii());
+        sweeper.field = Field::from_bombs(
+            6,
+            8,
+            &[Position(0, 0, 0), Position(2, 5, 0), Position(5, 7, 0)],
+        );
+
+        let mut cursor = Position(5, 0, 0);
+        for key in "e5wf6de".chars() {
+            cursor = sweeper.tick(&Key::Char(key), cursor).2;
+        }
+        assert_eq!(
+            snapshot(&mut sweeper),
+            include_str!("../tests/snapshots/ascii_field.txt")
+        );
+
+        sweeper.resize((14, 5));
+        sweeper.select(&cursor);
+        assert_eq!(
+            snapshot(&mut sweeper),
+            include_str!("../tests/snapshots/ascii_viewport.txt")
+        );
+    }
+
+    #[test]
+    fn test_invalid_custom_params() {
+        assert!(matches!(
+            Sweeper::new(Difficulty::Custom, None),
+            Err(Error::MissingCustomParams)
+        ));
+        assert!(matches!(
+            Sweeper::new(
+                Difficulty::Custom,
+                Some(CustomParams::with_defaults(Some(0), Some(9), None))
+            ),
+            Err(Error::InvalidDimensions { rows: 0, cols: 9 })
+        ));
+        assert!(matches!(
+            Sweeper::new(
+                Difficulty::Custom,
+                Some(CustomParams::with_defaults(
+                    Some(9),
+                    None,
+                    Some(Bombs::Percentile(1.5))
+                ))
+            ),
+            Err(Error::InvalidBombPercentile(_))
+        ));
+        assert!(matches!(
+            Sweeper::new(
+                Difficulty::Custom,
+                Some(CustomParams::with_defaults(
+                    Some(4),
+                    Some(4),
+                    Some(Bombs::Count(8))
+                ))
+            ),
+            Err(Error::InvalidBombCount { bombs: 8, max: 7 })
+        ));
+        assert!(Sweeper::new(
+            Difficulty::Custom,
+            Some(CustomParams::with_defaults(
+                Some(4),
+                Some(4),
+                Some(Bombs::Count(7))
+            ))
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_layers() {
+        let layered = |size, bombs| {
+            Sweeper::new(
+                Difficulty::Custom,
+                Some(CustomParams::layered(3, Some(size), None, Some(bombs))),
+            )
+        };
+        // The 3x3x3 block around the first click is always safe.
+        assert!(matches!(
+            layered(3, Bombs::Count(1)),
+            Err(Error::InvalidBombCount { bombs: 1, max: 0 })
+        ));
+        assert!(matches!(
+            layered(5, Bombs::Percentile(0.0)),
+            Err(Error::InvalidBombPercentile(_))
+        ));
+
+        let mut sweeper = layered(5, Bombs::Count(4))
+            .unwrap()
+            .with_seed(11)
+            .with_theme(Theme::ascii());
+        let field = &sweeper.field;
+        assert_eq!(field.get_neighbours(UnsafePosition(2, 2, 1)).len(), 26);
+        assert_eq!(field.get_neighbours(UnsafePosition(0, 0, 0)).len(), 7);
+
+        let mut cursor = Position(2, 2, 0);
+        for (key, expected) in [
+            ('>', Position(2, 2, 1)),
+            ('5', Position(2, 2, 1)),
+            ('>', Position(2, 2, 2)),
+            ('<', Position(2, 2, 1)),
+            ('G', Position(4, 2, 1)),
+            ('w', Position(3, 2, 1)),
+        ] {
+            cursor = sweeper.tick(&Key::Char(key), cursor).2;
+            assert_eq!(cursor, expected, "after `{}`", key);
+        }
+
+        // The opening spreads to the layers above and below, and numbers
+        // count the mines there too.
+        sweeper.reveal(&Position(2, 2, 1));
+        assert!(sweeper.field.tile(&Position(2, 2, 0)).revealed);
+        assert!(sweeper.field.tile(&Position(2, 2, 2)).revealed);
+        for tile in sweeper.field.tile_matrix.iter().flatten() {
+            if let TileKind::Safe(number) = tile.kind {
+                let neighbours = sweeper.field.get_neighbours(tile.position.to_unsafe());
+                let mines = neighbours.iter().filter(|t| t.is_bomb()).count();
+                assert_eq!(number as usize, mines);
+            }
+        }
+
+        sweeper.resize((80, 24));
+        let screen = snapshot(&mut sweeper);
+        assert!(
+            screen.contains("layer 1") && screen.contains("> layer 2 <"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("layer 3") && screen.contains("</>: switch layers"));
+    }
+
+    #[test]
+    fn test_cursor_move_only_redraws_changed_tiles() {
+        let mut sweeper = Sweeper::new(Difficulty::Nightmare, None).unwrap();
+        let mut cursor = Position(12, 27, 0);
+        sweeper.resize((200, 40));
+        sweeper.select(&cursor);
+
+        let mut stdout = Vec::new();
+        sweeper.display_field(&mut stdout).unwrap();
+        let full_frame = stdout.len();
+
+        for key in "dddsssaw".chars() {
+            let mut stdout = Vec::new();
+            cursor = sweeper.tick(&Key::Char(key), cursor).2;
+            sweeper.display_field(&mut stdout).unwrap();
+
+            assert!(
+                stdout.len() < 120,
+                "moving the cursor emitted {} bytes, a full frame is {}",
+                stdout.len(),
+                full_frame
+            );
+        }
     }
 }
@@ -0,0 +1,231 @@
+use std::io::{self, Read, Stdout, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::{cursor, screen, style};
+
+/// The terminal attributes from before entering raw mode, unset when raw
+/// mode was never entered.
+static ORIGINAL_ATTRIBUTES: OnceLock<libc::termios> = OnceLock::new();
+
+/// Whether the game switched to the alternate screen, see `restore`.
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// Whether the terminal was put in raw mode, see `is_raw`.
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+
+static TERMINATE_REQUESTED: AtomicBool = AtomicBool::new(false);
+static SUSPEND_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    /// SIGTERM, SIGHUP or SIGINT: restore the terminal and exit.
+    Terminate,
+    /// SIGTSTP: restore the terminal and stop until SIGCONT.
+    Suspend,
+}
+
+/// Remembers the current terminal attributes so that `restore` can bring
+/// them back. Must be called before entering raw mode.
+pub fn save() -> io::Result<()> {
+    let mut attributes = std::mem::MaybeUninit::<libc::termios>::uninit();
+
+    match unsafe { libc::tcgetattr(libc::STDOUT_FILENO, attributes.as_mut_ptr()) } {
+        0 => {
+            ORIGINAL_ATTRIBUTES.get_or_init(|| unsafe { attributes.assume_init() });
+            Ok(())
+        }
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Puts the terminal in raw mode until the returned guard is dropped.
+pub fn enter_raw_mode() -> io::Result<RawTerminal<Stdout>> {
+    let raw = io::stdout().into_raw_mode()?;
+    RAW_MODE.store(true, Ordering::SeqCst);
+    Ok(raw)
+}
+
+/// Whether output goes to a terminal in raw mode, where lines must end in
+/// `\r\n` to return to the first column.
+pub fn is_raw() -> bool {
+    RAW_MODE.load(Ordering::SeqCst)
+}
+
+/// Writes `message` to stderr, unless stderr is the terminal the game is
+/// drawn on.
+pub fn log(message: &str) {
+    if unsafe { libc::isatty(libc::STDERR_FILENO) } != 1 {
+        eprintln!("{}", message);
+    }
+}
+
+/// Whether stdout is a terminal rather than a file or a pipe.
+pub fn is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+/// How long a read waits for the first key, short enough for timers to be
+/// redrawn every second.
+const INPUT_WAIT: Duration = Duration::from_millis(50);
+
+/// Where keys come from: the controlling terminal, or stdin when the output
+/// is not a terminal, so keys can be piped in too.
+pub struct Input(Receiver<u8>);
+
+impl Input {
+    pub fn new(tty: bool) -> Self {
+        let (sender, bytes) = mpsc::channel();
+        thread::spawn(move || {
+            let source: Box<dyn Read> = match tty {
+                true => match termion::get_tty() {
+                    Ok(tty) => Box::new(tty),
+                    Err(_) => return,
+                },
+                false => Box::new(io::stdin()),
+            };
+            for byte in io::BufReader::new(source)
+                .bytes()
+                .map_while(|byte| byte.ok())
+            {
+                if sender.send(byte).is_err() {
+                    return;
+                }
+            }
+        });
+        Input(bytes)
+    }
+}
+
+impl Read for Input {
+    /// Reads the keys typed so far, waiting up to `INPUT_WAIT` for one if
+    /// there are none, or fails with `UnexpectedEof` once the input is
+    /// used up.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.0.recv_timeout(INPUT_WAIT) {
+            Ok(byte) => buf[0] = byte,
+            Err(RecvTimeoutError::Timeout) => return Ok(0),
+            Err(RecvTimeoutError::Disconnected) => return Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+
+        let mut read = 1;
+        while read < buf.len() {
+            match self.0.try_recv() {
+                Ok(byte) => {
+                    buf[read] = byte;
+                    read += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Terminal size used when the terminal reports none.
+const FALLBACK_SIZE: (u16, u16) = (80, 24);
+
+/// The size of the terminal as `(width, height)`. Serial consoles often
+/// report 0x0 and a redirected stdout has no size at all, both get
+/// `FALLBACK_SIZE`.
+pub fn size() -> (u16, u16) {
+    match termion::terminal_size() {
+        Ok((width, height)) if width > 0 && height > 0 => (width, height),
+        _ => FALLBACK_SIZE,
+    }
+}
+
+/// Switches to the alternate screen, which `restore` leaves again.
+pub fn enter_alternate_screen<W: Write>(stdout: &mut W) -> io::Result<()> {
+    ALTERNATE_SCREEN.store(true, Ordering::SeqCst);
+    write!(stdout, "{}", screen::ToAlternateScreen)
+}
+
+/// Undoes what the game did to the terminal: cooked mode if `save` was
+/// called, and default colours, a visible cursor and the main screen if it
+/// switched to the alternate screen. Plain output is left as printed.
+pub fn restore() {
+    if let Some(attributes) = ORIGINAL_ATTRIBUTES.get() {
+        unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, attributes) };
+    }
+
+    if ALTERNATE_SCREEN.load(Ordering::SeqCst) {
+        let mut stdout = io::stdout();
+        let _ = write!(
+            stdout,
+            "{}{}{}{}",
+            style::Reset,
+            termion::clear::All,
+            screen::ToMainScreen,
+            cursor::Show
+        );
+        let _ = stdout.flush();
+    }
+}
+
+/// Restores the terminal when dropped, so every way out of the game loop,
+/// including errors, leaves a usable shell behind.
+pub struct RestoreGuard;
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Restores the terminal before the default hook prints the panic message,
+/// which would otherwise be mangled by raw mode.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        default_hook(info);
+    }));
+}
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGTSTP => SUSPEND_REQUESTED.store(true, Ordering::SeqCst),
+        _ => TERMINATE_REQUESTED.store(true, Ordering::SeqCst),
+    }
+}
+
+/// Catches termination and stop signals so the game loop can restore the
+/// terminal first, see `pending_signal`.
+pub fn install_signal_handlers() {
+    for signal in [libc::SIGTERM, libc::SIGHUP, libc::SIGINT, libc::SIGTSTP] {
+        unsafe {
+            libc::signal(
+                signal,
+                handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            )
+        };
+    }
+}
+
+/// The signal received since the last call, termination first.
+pub fn pending_signal() -> Option<Signal> {
+    if TERMINATE_REQUESTED.swap(false, Ordering::SeqCst) {
+        return Some(Signal::Terminate);
+    }
+    if SUSPEND_REQUESTED.swap(false, Ordering::SeqCst) {
+        return Some(Signal::Suspend);
+    }
+    None
+}
+
+/// Restores the terminal and stops the process. Returns once the shell
+/// resumes it, after which the caller re-enters raw mode and redraws.
+pub fn suspend() {
+    restore();
+    unsafe { libc::raise(libc::SIGSTOP) };
+}
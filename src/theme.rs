@@ -0,0 +1,371 @@
+use std::env;
+use std::fmt;
+
+use termion::color;
+
+use crate::config::Ini;
+
+/// A terminal colour: an index into the 256 colour palette, and the exact
+/// RGB value to use instead on truecolor terminals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colour {
+    ansi: u8,
+    rgb: Option<(u8, u8, u8)>,
+}
+
+const fn ansi(ansi: u8) -> Colour {
+    Colour { ansi, rgb: None }
+}
+
+const fn rgb(ansi: u8, r: u8, g: u8, b: u8) -> Colour {
+    Colour {
+        ansi,
+        rgb: Some((r, g, b)),
+    }
+}
+
+/// Cursor and flag colours of co-op players, told apart by hue.
+const PLAYER_COLOURS: [Colour; 6] = [
+    rgb(39, 0, 114, 178),
+    rgb(208, 213, 94, 0),
+    rgb(35, 0, 158, 115),
+    rgb(175, 204, 121, 167),
+    rgb(220, 230, 159, 0),
+    rgb(117, 86, 180, 233),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Palette {
+    background: Colour,
+    text: Colour,
+    flag: Colour,
+    bomb: Colour,
+    numbers: [Colour; 8],
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ThemeError {
+    Parse(String),
+    Unknown(String),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeError::Parse(reason) => write!(f, "could not read config: {}", reason),
+            ThemeError::Unknown(name) => write!(
+                f,
+                "unknown theme `{}`, expected one of: {}",
+                name,
+                Theme::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// The characters used to draw tiles and scroll indicators.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Glyphs {
+    Unicode,
+    /// 7-bit characters only, for serial consoles and log captures.
+    Ascii,
+}
+
+impl Glyphs {
+    pub fn hidden(&self) -> char {
+        match self {
+            Glyphs::Unicode => '·',
+            Glyphs::Ascii => '#',
+        }
+    }
+
+    pub fn flag(&self) -> char {
+        match self {
+            Glyphs::Unicode => '⚑',
+            Glyphs::Ascii => 'F',
+        }
+    }
+
+    pub fn question(&self) -> char {
+        '?'
+    }
+
+    /// A safe tile whose number is not shown.
+    pub fn unknown(&self) -> char {
+        match self {
+            Glyphs::Unicode => '◌',
+            Glyphs::Ascii => '~',
+        }
+    }
+
+    /// A flag that turned out to be on a safe tile.
+    pub fn cross(&self) -> char {
+        match self {
+            Glyphs::Unicode => '✗',
+            Glyphs::Ascii => 'x',
+        }
+    }
+
+    /// A bomb that was stepped on.
+    pub fn exploded(&self) -> char {
+        match self {
+            Glyphs::Unicode => '✱',
+            Glyphs::Ascii => '@',
+        }
+    }
+
+    pub fn bomb(&self) -> char {
+        match self {
+            Glyphs::Unicode => '◆',
+            Glyphs::Ascii => '*',
+        }
+    }
+
+    pub fn empty(&self) -> char {
+        match self {
+            Glyphs::Unicode => ' ',
+            Glyphs::Ascii => '.',
+        }
+    }
+
+    /// Arrows pointing up, down, left and right.
+    pub fn arrows(&self) -> [char; 4] {
+        match self {
+            Glyphs::Unicode => ['▲', '▼', '◀', '▶'],
+            Glyphs::Ascii => ['^', 'v', '<', '>'],
+        }
+    }
+}
+
+/// The colours and glyphs used to draw the field. A theme without a palette
+/// emits no colour escape codes at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub glyphs: Glyphs,
+    palette: Option<Palette>,
+    truecolor: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+impl Theme {
+    pub const NAMES: [&'static str; 4] = [
+        "classic",
+        "high-contrast",
+        "deuteranopia-safe",
+        "monochrome",
+    ];
+
+    pub fn classic() -> Self {
+        Self {
+            name: "classic",
+            palette: Some(Palette {
+                background: ansi(0),
+                text: ansi(7),
+                flag: ansi(7),
+                bomb: ansi(1),
+                numbers: [
+                    ansi(4),
+                    ansi(10),
+                    ansi(1),
+                    ansi(5),
+                    ansi(11),
+                    ansi(14),
+                    ansi(8),
+                    ansi(12),
+                ],
+            }),
+            glyphs: Glyphs::Unicode,
+            truecolor: false,
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            name: "high-contrast",
+            palette: Some(Palette {
+                background: rgb(16, 0, 0, 0),
+                text: rgb(231, 255, 255, 255),
+                flag: rgb(226, 255, 255, 0),
+                bomb: rgb(196, 255, 0, 0),
+                numbers: [
+                    rgb(51, 0, 255, 255),
+                    rgb(46, 0, 255, 0),
+                    rgb(201, 255, 0, 255),
+                    rgb(226, 255, 255, 0),
+                    rgb(208, 255, 128, 0),
+                    rgb(33, 0, 128, 255),
+                    rgb(231, 255, 255, 255),
+                    rgb(250, 192, 192, 192),
+                ],
+            }),
+            glyphs: Glyphs::Unicode,
+            truecolor: false,
+        }
+    }
+
+    /// Based on the Okabe-Ito palette, which stays distinguishable for
+    /// deuteranopia and protanopia.
+    pub fn deuteranopia_safe() -> Self {
+        Self {
+            name: "deuteranopia-safe",
+            palette: Some(Palette {
+                background: rgb(16, 0, 0, 0),
+                text: rgb(252, 208, 208, 208),
+                flag: rgb(220, 240, 228, 66),
+                bomb: rgb(166, 213, 94, 0),
+                numbers: [
+                    rgb(74, 86, 180, 233),
+                    rgb(214, 230, 159, 0),
+                    rgb(25, 0, 114, 178),
+                    rgb(175, 204, 121, 167),
+                    rgb(36, 0, 158, 115),
+                    rgb(227, 240, 228, 66),
+                    rgb(231, 255, 255, 255),
+                    rgb(244, 128, 128, 128),
+                ],
+            }),
+            glyphs: Glyphs::Unicode,
+            truecolor: false,
+        }
+    }
+
+    pub fn monochrome() -> Self {
+        Self {
+            name: "monochrome",
+            palette: None,
+            glyphs: Glyphs::Unicode,
+            truecolor: false,
+        }
+    }
+
+    /// Monochrome with 7-bit glyphs: the output contains no escape codes.
+    pub fn ascii() -> Self {
+        Self {
+            name: "ascii",
+            glyphs: Glyphs::Ascii,
+            ..Self::monochrome()
+        }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.palette.is_none() && self.glyphs == Glyphs::Ascii
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Self::classic()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "deuteranopia-safe" => Some(Self::deuteranopia_safe()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// Picks the theme from `--theme`, then `NO_COLOR`, then the `[display]`
+    /// section of the config file. Truecolor is enabled when `COLORTERM`
+    /// advertises it, unless the config sets `truecolor = false`.
+    pub fn load(theme_override: Option<&str>, settings: &Ini) -> Result<Self, ThemeError> {
+        let display = settings.get("display");
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+
+        let name = match (theme_override, no_color) {
+            (Some(name), _) => name,
+            (None, true) => "monochrome",
+            (None, false) => display
+                .and_then(|d| d.get("theme"))
+                .map_or("classic", String::as_str),
+        };
+
+        let truecolor = match display.and_then(|d| d.get("truecolor")) {
+            Some(value) => matches!(value.as_str(), "true" | "yes" | "1"),
+            None => env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit"),
+        };
+
+        Self::by_name(name)
+            .map(|theme| theme.with_truecolor(truecolor))
+            .ok_or_else(|| ThemeError::Unknown(name.to_string()))
+    }
+
+    pub fn with_truecolor(self, truecolor: bool) -> Self {
+        Self { truecolor, ..self }
+    }
+
+    fn fg(&self, pick: impl Fn(&Palette) -> Colour) -> String {
+        self.palette.as_ref().map_or(String::new(), |palette| {
+            match (pick(palette).rgb, self.truecolor) {
+                (Some((r, g, b)), true) => color::Fg(color::Rgb(r, g, b)).to_string(),
+                _ => color::Fg(color::AnsiValue(pick(palette).ansi)).to_string(),
+            }
+        })
+    }
+
+    /// The colour of co-op player `id`, shared by all themes with colours.
+    pub fn player(&self, id: usize) -> String {
+        self.fg(|_| PLAYER_COLOURS[id % PLAYER_COLOURS.len()])
+    }
+
+    fn bg(&self, pick: impl Fn(&Palette) -> Colour) -> String {
+        self.palette.as_ref().map_or(String::new(), |palette| {
+            match (pick(palette).rgb, self.truecolor) {
+                (Some((r, g, b)), true) => color::Bg(color::Rgb(r, g, b)).to_string(),
+                _ => color::Bg(color::AnsiValue(pick(palette).ansi)).to_string(),
+            }
+        })
+    }
+
+    pub fn background(&self) -> String {
+        self.bg(|palette| palette.background)
+    }
+
+    /// A bomb that was stepped on, drawn inverted in the bomb colour.
+    pub fn exploded(&self) -> String {
+        format!(
+            "{}{}",
+            self.bg(|palette| palette.bomb),
+            self.fg(|palette| palette.background)
+        )
+    }
+
+    pub fn text(&self) -> String {
+        self.fg(|palette| palette.text)
+    }
+
+    pub fn flag(&self) -> String {
+        self.fg(|palette| palette.flag)
+    }
+
+    pub fn bomb(&self) -> String {
+        self.fg(|palette| palette.bomb)
+    }
+
+    pub fn number(&self, bombs: u8) -> String {
+        match bombs {
+            1..=8 => self.fg(|palette| palette.numbers[bombs as usize - 1]),
+            // Only several mines on a tile add up to more than 8.
+            9.. => self.fg(|palette| palette.numbers[7]),
+            _ => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_escapes() {
+        assert_eq!(Theme::classic().number(1), "\x1b[38;5;4m");
+        assert_eq!(
+            Theme::deuteranopia_safe().with_truecolor(true).number(2),
+            "\x1b[38;2;230;159;0m"
+        );
+        assert_eq!(Theme::monochrome().background(), "");
+        assert_eq!(Theme::monochrome().bomb(), "");
+    }
+}
@@ -1,19 +1,60 @@
+use crate::rules::RuleSet;
 use crate::sweeper::Position;
-use termion::color;
+use crate::theme::Theme;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TileKind {
+    /// The number of mines around the tile.
     Safe(u8),
-    Bomb,
+    /// A tile with this many mines, only ever one under the classic rules.
+    Bomb(u8),
     Empty,
 }
 
+/// What a revealed number shows, dealt by `RuleSet::clue` and drawn through
+/// `RuleSet::shown_number`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clue {
+    /// The number of mines around the tile.
+    Number,
+    /// This number instead.
+    Lie(u8),
+    /// Nothing but that the tile is safe.
+    Hidden,
+}
+
+/// What the player has put on a hidden tile. Only flags count as mines,
+/// question marks are notes that do not block revealing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mark {
+    None,
+    /// The number of mines the player thinks the tile holds.
+    Flag(u8),
+    Question,
+}
+
+impl Mark {
+    /// The mark after pressing the flag key once more, counting flags up to
+    /// `max_mines` before the question mark.
+    pub fn next(&self, max_mines: u8) -> Self {
+        match self {
+            Mark::None => Mark::Flag(1),
+            Mark::Flag(flags) if *flags < max_mines => Mark::Flag(flags + 1),
+            Mark::Flag(_) => Mark::Question,
+            Mark::Question => Mark::None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Tile {
     pub kind: TileKind,
+    pub clue: Clue,
     pub neighbours: Vec<Tile>,
     pub revealed: bool,
-    pub flagged: bool,
+    pub mark: Mark,
+    /// A bomb that was stepped on.
+    pub exploded: bool,
     pub selected: bool,
     pub position: Position,
 }
@@ -26,10 +67,12 @@ impl Tile {
                 (TileKind::Safe(0), 0) => TileKind::Empty,
                 _ => kind,
             },
+            clue: Clue::Number,
             neighbours,
             position,
             revealed: false,
-            flagged: false,
+            mark: Mark::None,
+            exploded: false,
             selected: false,
         }
     }
@@ -38,16 +81,24 @@ impl Tile {
         Self::new(TileKind::Empty, vec![], position)
     }
 
+    /// A tile numbered with the mines on `neighbours`.
     pub fn new_safe(position: Position, neighbours: Vec<Tile>) -> Self {
         Self::new(
-            TileKind::Safe(neighbours.len().try_into().unwrap()),
+            TileKind::Safe(neighbours.iter().map(Tile::mines).sum()),
             neighbours,
             position,
         )
     }
 
-    pub fn new_bomb(position: Position) -> Self {
-        Self::new(TileKind::Bomb, vec![], position)
+    pub fn new_bomb(position: Position, mines: u8) -> Self {
+        Self::new(TileKind::Bomb(mines), vec![], position)
+    }
+
+    pub fn with_clue(&self, clue: Clue) -> Self {
+        Self {
+            clue,
+            ..self.clone()
+        }
     }
 
     pub fn set_neighbours(&self, neighbours: Vec<Tile>) -> Self {
@@ -64,20 +115,39 @@ impl Tile {
         }
     }
 
-    pub fn flag(&self) -> Self {
+    pub fn explode(&self) -> Self {
+        Self {
+            revealed: true,
+            exploded: true,
+            ..self.clone()
+        }
+    }
+
+    /// A bomb stepped on in a casual game: flagged, and it stays that way.
+    pub fn defuse(&self) -> Self {
         Self {
-            flagged: true,
+            revealed: false,
+            exploded: true,
+            mark: Mark::Flag(self.mines()),
             ..self.clone()
         }
     }
 
-    pub fn unflag(&self) -> Self {
+    pub fn with_mark(&self, mark: Mark) -> Self {
         Self {
-            flagged: false,
+            mark,
             ..self.clone()
         }
     }
 
+    pub fn flag(&self) -> Self {
+        self.with_mark(Mark::Flag(1))
+    }
+
+    pub fn unmark(&self) -> Self {
+        self.with_mark(Mark::None)
+    }
+
     pub fn select(&self) -> Self {
         Self {
             selected: true,
@@ -92,52 +162,101 @@ impl Tile {
         }
     }
 
+    pub fn is_flagged(&self) -> bool {
+        matches!(self.mark, Mark::Flag(_))
+    }
+
+    /// The mines flagged on the tile.
+    pub fn flags(&self) -> u8 {
+        match self.mark {
+            Mark::Flag(flags) => flags,
+            _ => 0,
+        }
+    }
+
+    /// The mines on the tile.
+    pub fn mines(&self) -> u8 {
+        match self.kind {
+            TileKind::Bomb(mines) => mines,
+            _ => 0,
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.kind == TileKind::Empty
     }
 
     pub fn is_safe(&self) -> bool {
-        match self.kind {
-            TileKind::Safe(_) => true,
-            _ => false,
-        }
+        matches!(self.kind, TileKind::Safe(_))
     }
     pub fn is_bomb(&self) -> bool {
-        self.kind == TileKind::Bomb
-    }
-
-    pub fn repr(&self) -> String {
-        match self.flagged {
-            true => format!("{}{}?", color::Bg(color::Black), color::Fg(color::White)),
-            _ => match self.revealed {
-                false => format!("{}{}·", color::Bg(color::Black), color::Fg(color::White)),
-                true => match self.kind {
-                    TileKind::Bomb => {
-                        format!("{}{}◆", color::Bg(color::Black), color::Fg(color::Red))
-                    }
-                    TileKind::Empty => format!("{} ", color::Bg(color::Black)),
-                    TileKind::Safe(bombs) => format!(
-                        "{}{}{}",
-                        color::Bg(color::Black),
-                        match bombs {
-                            1 => color::Fg(color::Blue).to_string(),
-                            2 => color::Fg(color::LightGreen).to_string(),
-                            3 => color::Fg(color::Red).to_string(),
-                            4 => color::Fg(color::Magenta).to_string(),
-                            5 => color::Fg(color::LightYellow).to_string(),
-                            6 => color::Fg(color::LightCyan).to_string(),
-                            7 => color::Fg(color::LightBlack).to_string(),
-                            8 => color::Fg(color::LightBlue).to_string(),
-                            _ => "".to_string(),
-                        },
-                        bombs
-                    ),
-                },
+        matches!(self.kind, TileKind::Bomb(_))
+    }
+
+    /// The colours and text of `repr`. The text is a single character, or
+    /// two for a number over 9 and for a flag or bomb of several mines.
+    fn face(&self, theme: &Theme) -> (String, String) {
+        let glyphs = theme.glyphs;
+        let counted = |glyph: char, count: u8| match count {
+            0 | 1 => glyph.to_string(),
+            count => format!("{}{}", glyph, count),
+        };
+
+        match (self.mark, self.revealed, self.kind) {
+            _ if self.exploded => (theme.exploded(), counted(glyphs.exploded(), self.mines())),
+            // Everything is revealed after a loss: flags on safe tiles were wrong.
+            (Mark::Flag(_), true, TileKind::Safe(_) | TileKind::Empty) => (
+                format!("{}{}", theme.background(), theme.bomb()),
+                glyphs.cross().to_string(),
+            ),
+            (Mark::Flag(flags), _, _) => (
+                format!("{}{}", theme.background(), theme.flag()),
+                counted(glyphs.flag(), flags),
+            ),
+            (Mark::Question, false, _) => (
+                format!("{}{}", theme.background(), theme.text()),
+                glyphs.question().to_string(),
+            ),
+            (_, false, _) => (
+                format!("{}{}", theme.background(), theme.text()),
+                glyphs.hidden().to_string(),
+            ),
+            (_, true, TileKind::Bomb(mines)) => (
+                format!("{}{}", theme.background(), theme.bomb()),
+                counted(glyphs.bomb(), mines),
+            ),
+            (_, true, TileKind::Empty) => (theme.background(), glyphs.empty().to_string()),
+            (_, true, TileKind::Safe(bombs)) => match RuleSet::shown_number(self.clue, bombs) {
+                Some(shown) => (
+                    format!("{}{}", theme.background(), theme.number(shown)),
+                    shown.to_string(),
+                ),
+                None => (
+                    format!("{}{}", theme.background(), theme.text()),
+                    glyphs.unknown().to_string(),
+                ),
             },
         }
     }
 
-    pub fn padded_repr(&self) -> String {
-        format!(" {} ", self.repr())
+    pub fn repr(&self, theme: &Theme) -> String {
+        let (colours, text) = self.face(theme);
+
+        match self.exploded {
+            true => format!("{}{}{}", colours, text, theme.background()),
+            false => format!("{}{}", colours, text),
+        }
+    }
+
+    /// Whether `repr` takes two columns, leaving no room for padding after it.
+    pub fn is_wide(&self, theme: &Theme) -> bool {
+        self.face(theme).1.chars().count() > 1
+    }
+
+    pub fn padded_repr(&self, theme: &Theme) -> String {
+        match self.is_wide(theme) {
+            true => format!(" {}", self.repr(theme)),
+            false => format!(" {} ", self.repr(theme)),
+        }
     }
 }
@@ -0,0 +1,264 @@
+use std::io::{self, Write};
+
+use termion::event::Key;
+
+use crate::field::Field;
+use crate::keymap::{Action, Keymap};
+use crate::layout;
+use crate::renderer::{Cell, Renderer};
+use crate::sweeper::{Difficulty, Position, Sweeper};
+use crate::theme::Theme;
+use crate::tile::Mark;
+
+/// What the player has to do to finish a step.
+#[derive(Debug, Clone, PartialEq)]
+enum Goal {
+    Move(Position),
+    Reveal(Position),
+    Flag(Position),
+    /// Reveal on this number, opening all its unflagged neighbours.
+    Chord(Position),
+    /// Reveal every safe tile, in any way.
+    Clear,
+}
+
+impl Goal {
+    fn is_met(&self, field: &Field, cursor: &Position) -> bool {
+        let tile = |Position(row, col, _): &Position| &field.tile_matrix[*row][*col];
+
+        match self {
+            Goal::Move(position) => cursor == position,
+            Goal::Reveal(position) => tile(position).revealed,
+            Goal::Flag(position) => tile(position).is_flagged(),
+            Goal::Chord(position) => {
+                cursor == position
+                    && field
+                        .get_neighbours(position.to_unsafe())
+                        .iter()
+                        .all(|tile| tile.revealed || tile.is_bomb())
+            }
+            Goal::Clear => field.is_cleared(),
+        }
+    }
+}
+
+struct Step {
+    /// A new board for this step, or `None` to go on with the last one.
+    layout: Option<&'static str>,
+    /// Key names in braces are filled in from the keymap.
+    text: &'static str,
+    goal: Goal,
+}
+
+const STEPS: [Step; 7] = [
+    Step {
+        layout: Some(".....\n.....\n...*.\n.....\n*....\n"),
+        text: "Welcome! The board hides mines under its tiles.\n\
+               Move the cursor to the top left corner with {up} and {left}.",
+        goal: Goal::Move(Position(0, 0, 0)),
+    },
+    Step {
+        layout: None,
+        text: "Press {reveal} to reveal the tile under the cursor. A tile with no mines\n\
+               around it opens its neighbours too, the numbers count the mines around them.",
+        goal: Goal::Reveal(Position(0, 0, 0)),
+    },
+    Step {
+        layout: Some("oooo.\noooo*\nooooo\n"),
+        text: "The 1 in the bottom right corner has a single hidden neighbour,\n\
+               so that tile is a mine. Move there and flag it with {flag}.",
+        goal: Goal::Flag(Position(1, 4, 0)),
+    },
+    Step {
+        layout: None,
+        text: "The 1 at the end of the top row has its mine flagged now. Press {reveal} on it\n\
+               to chord: all of its unflagged neighbours are revealed at once.",
+        goal: Goal::Chord(Position(0, 3, 0)),
+    },
+    Step {
+        layout: Some(".*.*.\nooooo\nooooo\n"),
+        text: "A common pattern: the 1 on the left edge has two hidden neighbours, the 1 next to\n\
+               it has the same two and one more. Its mine is among the first two, so the third\n\
+               one is safe. Reveal the tile above the 2.",
+        goal: Goal::Reveal(Position(0, 2, 0)),
+    },
+    Step {
+        layout: None,
+        text: "The 2 has two hidden neighbours left, so both are mines.\n\
+               Flag the one on the left.",
+        goal: Goal::Flag(Position(0, 1, 0)),
+    },
+    Step {
+        layout: None,
+        text: "Finish the board on your own: reveal every safe tile left.",
+        goal: Goal::Clear,
+    },
+];
+
+/// Teaches the rules and controls step by step on fixed boards. Moves that
+/// do not lead to the step's goal are taken back.
+#[derive(Debug)]
+pub struct Tutorial {
+    keymap: Keymap,
+    theme: Theme,
+    renderer: Renderer,
+    size: (u16, u16),
+    sweeper: Sweeper,
+    cursor: Position,
+    step: usize,
+    /// The last move was taken back.
+    mistake: bool,
+}
+
+impl Tutorial {
+    pub fn new(keymap: Keymap, theme: Theme, size: (u16, u16)) -> Self {
+        let mut tutorial = Self {
+            sweeper: Sweeper::from_field(Difficulty::Custom, Field::from_bombs(1, 1, &[])),
+            keymap,
+            theme,
+            renderer: Renderer::default(),
+            size,
+            cursor: Position(0, 0, 0),
+            step: 0,
+            mistake: false,
+        };
+        tutorial.load_step();
+        tutorial
+    }
+
+    fn load_step(&mut self) {
+        let layout = match STEPS.get(self.step).and_then(|step| step.layout) {
+            Some(layout) => layout,
+            None => return,
+        };
+        let field = layout::parse(layout).expect("tutorial boards are valid layouts");
+
+        self.cursor = Position(field.rows / 2, field.cols / 2, 0);
+        self.sweeper = Sweeper::from_field(Difficulty::Custom, field)
+            .with_keymap(self.keymap.clone())
+            .with_theme(self.theme.clone());
+        self.resize(self.size);
+    }
+
+    pub fn resize(&mut self, size: (u16, u16)) {
+        self.size = size;
+        self.sweeper.resize(size);
+        self.sweeper.select(&self.cursor);
+        self.renderer.invalidate();
+    }
+
+    pub fn redraw(&mut self) {
+        self.renderer.invalidate();
+    }
+
+    /// Plays `key` through `Sweeper::tick`, returning whether to quit.
+    pub fn tick(&mut self, key: &Key) -> bool {
+        if self.keymap.action(key) == Some(Action::Quit) {
+            return true;
+        }
+        let goal = match STEPS.get(self.step) {
+            Some(step) => &step.goal,
+            None => return false,
+        };
+
+        let marks = |field: &Field| -> Vec<(bool, Mark)> {
+            field
+                .tile_matrix
+                .iter()
+                .flatten()
+                .map(|tile| (tile.revealed, tile.mark))
+                .collect()
+        };
+        let before = self.sweeper.field.clone();
+        self.cursor = self.sweeper.tick(key, self.cursor.clone()).2;
+        let field = &self.sweeper.field;
+
+        self.mistake = false;
+        if goal.is_met(field, &self.cursor) {
+            self.step += 1;
+            self.load_step();
+        } else if marks(field) != marks(&before) && (*goal != Goal::Clear || field.is_exploded()) {
+            self.sweeper.field = before;
+            self.sweeper.select(&self.cursor);
+            self.mistake = true;
+        }
+
+        false
+    }
+
+    fn text(&self) -> String {
+        let text = match STEPS.get(self.step) {
+            Some(step) => step.text,
+            None => "That's all there is to it, have fun! Press {quit} to leave.",
+        };
+
+        [
+            ("{up}", Action::MoveUp),
+            ("{left}", Action::MoveLeft),
+            ("{reveal}", Action::Reveal),
+            ("{flag}", Action::Flag),
+            ("{quit}", Action::Quit),
+        ]
+        .iter()
+        .fold(text.to_string(), |text, (name, action)| {
+            text.replace(name, &self.keymap.key_hint(*action))
+        })
+    }
+
+    pub fn display<W: Write>(&mut self, stdout: &mut W) -> io::Result<()> {
+        let mut frame = self
+            .sweeper
+            .field
+            .render(&self.sweeper.viewport, &self.theme);
+        let line = |text: String| {
+            vec![Cell::new(
+                text.chars().count() + 1,
+                format!("{} {}", self.theme.text(), text),
+            )]
+        };
+
+        frame.push(line(format!(
+            "Step {}/{}{}",
+            (self.step + 1).min(STEPS.len()),
+            STEPS.len(),
+            match self.mistake {
+                true => "  Not quite, that move was taken back.",
+                false => "",
+            }
+        )));
+        frame.extend(self.text().lines().map(|text| line(text.to_string())));
+
+        match self.theme.is_plain() {
+            true => Renderer::draw_plain(stdout, frame),
+            false => self.renderer.draw(stdout, frame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tutorial() {
+        let mut tutorial = Tutorial::new(Keymap::default(), Theme::ascii(), (80, 24));
+        let play = |tutorial: &mut Tutorial, keys: &str| {
+            for key in keys.chars() {
+                assert!(!tutorial.tick(&Key::Char(key)));
+            }
+            tutorial.step
+        };
+
+        assert_eq!(play(&mut tutorial, " "), 0);
+        assert_eq!(play(&mut tutorial, "wwaa"), 1);
+        assert_eq!(play(&mut tutorial, " "), 2);
+        assert_eq!(play(&mut tutorial, "ddf"), 3);
+        assert_eq!(play(&mut tutorial, "wa "), 4);
+        // Revealing a tile that is not known to be safe yet is taken back.
+        assert_eq!(play(&mut tutorial, "waa "), 4);
+        assert!(tutorial.mistake);
+        assert_eq!(play(&mut tutorial, "dd "), 5);
+        assert_eq!(play(&mut tutorial, "af"), 6);
+        assert_eq!(play(&mut tutorial, "a dddd "), STEPS.len());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::sweeper::Position;
+
+/// Width of a single rendered tile, see `Tile::padded_repr`.
+pub const TILE_WIDTH: usize = 3;
+
+/// Columns between two layers drawn side by side.
+pub const LAYER_GAP: usize = 2;
+
+/// Columns taken by the left and right scroll indicators.
+const HORIZONTAL_CHROME: usize = 2;
+
+/// Lines taken by the top and bottom scroll indicators and the status line.
+const VERTICAL_CHROME: usize = 3;
+
+/// The window of the field that is currently drawn on screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Viewport {
+    pub top: usize,
+    pub left: usize,
+    pub rows: usize,
+    pub cols: usize,
+    /// The leftmost layer drawn, and how many are drawn side by side.
+    pub first_layer: usize,
+    pub layers: usize,
+    /// The layer of the cursor last followed.
+    pub cursor_layer: usize,
+    field_layers: usize,
+    field_rows: usize,
+    field_cols: usize,
+}
+
+impl Viewport {
+    /// A viewport covering the whole field.
+    pub fn new(field_layers: usize, field_rows: usize, field_cols: usize) -> Self {
+        Self {
+            top: 0,
+            left: 0,
+            rows: field_rows,
+            cols: field_cols,
+            first_layer: 0,
+            layers: field_layers,
+            cursor_layer: 0,
+            field_layers,
+            field_rows,
+            field_cols,
+        }
+    }
+
+    /// Fits the viewport inside a terminal of `width` x `height` characters,
+    /// keeping the current scroll offsets when possible. Layers that do not
+    /// fit next to each other are scrolled through like columns, a layered
+    /// field also takes a line for the layer names.
+    pub fn resize(&self, width: u16, height: u16) -> Self {
+        let header = usize::from(self.field_layers > 1);
+        let rows = (height as usize)
+            .saturating_sub(VERTICAL_CHROME + header)
+            .max(1);
+        let width = (width as usize).saturating_sub(HORIZONTAL_CHROME);
+        let cols = (width / TILE_WIDTH).max(1);
+        let layers = (width + LAYER_GAP) / (self.field_cols * TILE_WIDTH + LAYER_GAP);
+
+        Self {
+            rows: rows.min(self.field_rows),
+            cols: cols.min(self.field_cols),
+            layers: layers.clamp(1, self.field_layers),
+            ..self.clone()
+        }
+        .clamp()
+    }
+
+    /// Scrolls the viewport so that `cursor` is visible.
+    pub fn follow(&self, cursor: &Position) -> Self {
+        let Position(row, col, layer) = *cursor;
+        let mut viewport = Self {
+            cursor_layer: layer,
+            ..self.clone()
+        };
+
+        if row < viewport.top {
+            viewport.top = row;
+        }
+        if row >= viewport.top + viewport.rows {
+            viewport.top = row + 1 - viewport.rows;
+        }
+
+        if col < viewport.left {
+            viewport.left = col;
+        }
+        if col >= viewport.left + viewport.cols {
+            viewport.left = col + 1 - viewport.cols;
+        }
+
+        if layer < viewport.first_layer {
+            viewport.first_layer = layer;
+        }
+        if layer >= viewport.first_layer + viewport.layers {
+            viewport.first_layer = layer + 1 - viewport.layers;
+        }
+
+        viewport.clamp()
+    }
+
+    fn clamp(&self) -> Self {
+        Self {
+            top: self.top.min(self.field_rows - self.rows),
+            left: self.left.min(self.field_cols - self.cols),
+            first_layer: self.first_layer.min(self.field_layers - self.layers),
+            ..self.clone()
+        }
+    }
+
+    pub fn row_range(&self) -> std::ops::Range<usize> {
+        self.top..self.top + self.rows
+    }
+
+    pub fn col_range(&self) -> std::ops::Range<usize> {
+        self.left..self.left + self.cols
+    }
+
+    pub fn layer_range(&self) -> std::ops::Range<usize> {
+        self.first_layer..self.first_layer + self.layers
+    }
+
+    /// Columns taken by the visible tiles and the gaps between layers.
+    pub fn width(&self) -> usize {
+        self.layers * self.cols * TILE_WIDTH + (self.layers - 1) * LAYER_GAP
+    }
+
+    pub fn can_scroll_up(&self) -> bool {
+        self.top > 0
+    }
+
+    pub fn can_scroll_down(&self) -> bool {
+        self.top + self.rows < self.field_rows
+    }
+
+    pub fn can_scroll_left(&self) -> bool {
+        self.left > 0 || self.first_layer > 0
+    }
+
+    pub fn can_scroll_right(&self) -> bool {
+        self.left + self.cols < self.field_cols
+            || self.first_layer + self.layers < self.field_layers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewport_follows_cursor() {
+        let viewport = Viewport::new(1, 25, 55).resize(80, 13);
+        assert_eq!((viewport.rows, viewport.cols), (10, 26));
+
+        let viewport = viewport.follow(&Position(24, 54, 0));
+        assert_eq!((viewport.top, viewport.left), (15, 29));
+        assert!(viewport.can_scroll_up() && viewport.can_scroll_left());
+        assert!(!viewport.can_scroll_down() && !viewport.can_scroll_right());
+
+        let viewport = viewport.follow(&Position(0, 30, 0));
+        assert_eq!((viewport.top, viewport.left), (0, 29));
+    }
+
+    #[test]
+    fn test_viewport_scrolls_layers() {
+        // Two 8-column layers fit next to each other in 60 columns.
+        let viewport = Viewport::new(4, 20, 8).resize(60, 13);
+        assert_eq!((viewport.rows, viewport.cols, viewport.layers), (9, 8, 2));
+        assert_eq!(viewport.width(), 2 * 8 * TILE_WIDTH + LAYER_GAP);
+
+        let viewport = viewport.follow(&Position(19, 0, 3));
+        assert_eq!((viewport.top, viewport.layer_range()), (11, 2..4));
+        assert!(viewport.can_scroll_left() && !viewport.can_scroll_right());
+
+        let viewport = viewport.follow(&Position(19, 0, 1));
+        assert_eq!(viewport.layer_range(), 1..3);
+    }
+}
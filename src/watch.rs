@@ -0,0 +1,131 @@
+//! Spectating: a running game publishes what the player sees to anyone
+//! who connects, using the co-op server's `board` and `cursor` lines with
+//! the player as id 0. Watchers never send anything.
+
+use std::collections::HashMap;
+
+use crate::coop::{ClientMessage, ServerMessage, VisibleBoard};
+use crate::error::Result;
+use crate::net::{Connection, Listener};
+use crate::sweeper::{Position, Sweeper};
+
+/// The id the watched player's cursor is sent with.
+pub const PLAYER: usize = 0;
+
+/// Sends the board and cursor of a game to its watchers.
+#[derive(Debug)]
+pub struct Publisher {
+    listener: Listener,
+    watchers: Vec<Connection<ServerMessage, ClientMessage>>,
+    board: Option<VisibleBoard>,
+    cursor: Option<Position>,
+}
+
+impl Publisher {
+    pub fn new(listener: Listener) -> Result<Self> {
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            watchers: vec![],
+            board: None,
+            cursor: None,
+        })
+    }
+
+    pub fn address(&self) -> String {
+        self.listener.address()
+    }
+
+    /// Takes on new watchers, sending them the last published board and cursor.
+    pub fn accept(&mut self) -> Result<()> {
+        while let Ok(stream) = self.listener.accept() {
+            stream.set_nonblocking(true)?;
+            let mut watcher = Connection::new(stream)?;
+            let sent = self
+                .board
+                .iter()
+                .map(|board| ServerMessage::Board(board.clone()))
+                .chain(self.cursor.iter().map(|cursor| ServerMessage::Cursor {
+                    id: PLAYER,
+                    position: cursor.clone(),
+                }))
+                .all(|message| watcher.send(&message).is_ok());
+            if sent {
+                self.watchers.push(watcher);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the watchers whatever changed since the last call. Watchers
+    /// that went away or cannot keep up are dropped.
+    pub fn publish(&mut self, sweeper: &Sweeper, cursor: &Position) {
+        let board = VisibleBoard::from_field(&sweeper.field, sweeper.state(), &HashMap::new());
+        let mut messages = vec![];
+        if self.board.as_ref() != Some(&board) {
+            messages.push(ServerMessage::Board(board.clone()));
+        }
+        if self.cursor.as_ref() != Some(cursor) {
+            messages.push(ServerMessage::Cursor {
+                id: PLAYER,
+                position: cursor.clone(),
+            });
+        }
+
+        self.watchers
+            .retain_mut(|watcher| messages.iter().all(|message| watcher.send(message).is_ok()));
+        self.board = Some(board);
+        self.cursor = Some(cursor.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use std::collections::HashMap;
+
+    use crate::coop::Client;
+    use crate::net;
+    use crate::sweeper::Difficulty;
+
+    use super::*;
+
+    fn wait_for(client: &mut Client, until: impl Fn(&Client) -> bool) {
+        while !until(client) {
+            client.poll().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_watch_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("rust-sweeper-{}.sock", std::process::id()));
+        let addr = path.display().to_string();
+        let mut publisher = Publisher::new(net::bind(&addr).unwrap()).unwrap();
+        let mut sweeper = Sweeper::new(Difficulty::Easy, None).unwrap().with_seed(3);
+        let visible = |sweeper: &Sweeper| {
+            VisibleBoard::from_field(&sweeper.field, sweeper.state(), &HashMap::new())
+        };
+
+        publisher.publish(&sweeper, &Position(0, 0, 0));
+        let mut watcher = Client::connect(&addr).unwrap();
+        publisher.accept().unwrap();
+        wait_for(&mut watcher, |client| client.board.is_some());
+        assert_eq!(watcher.board, Some(visible(&sweeper)));
+
+        sweeper.reveal(&Position(4, 4, 0));
+        publisher.publish(&sweeper, &Position(4, 4, 0));
+        wait_for(&mut watcher, |client| {
+            client.cursors.get(&PLAYER) == Some(&Position(4, 4, 0))
+        });
+        assert_eq!(watcher.board, Some(visible(&sweeper)));
+
+        drop(publisher);
+        wait_for(&mut watcher, |client| client.closed);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,49 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Plays `keys` on an easy board with stdin and stdout redirected, as in
+/// `rust-sweeper --ascii > board.txt`, and returns what was printed.
+fn play_redirected(keys: &str) -> String {
+    let home = std::env::temp_dir().join("rust-sweeper-ascii-test");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-sweeper"))
+        .args(["--ascii", "--difficulty", "easy", "--seed", "1"])
+        .env("XDG_DATA_HOME", &home)
+        .env("XDG_CONFIG_HOME", &home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(keys.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_ascii_to_a_file() {
+    let output = play_redirected("eq");
+
+    assert!(!output.contains('\x1b'), "escape codes in {:?}", output);
+    assert!(!output.contains('\r'), "CRLF line endings in {:?}", output);
+    assert!(output.contains("  #  #  1  1  .  .  .  .  ."));
+    assert!(output.trim_end().ends_with("seed 1"));
+}
+
+#[test]
+fn test_ascii_ends_with_stdin() {
+    let output = play_redirected("");
+
+    assert!(output.contains("0/10 flagged"));
+}
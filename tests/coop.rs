@@ -0,0 +1,123 @@
+use std::thread;
+use std::time::Duration;
+
+use rust_sweeper::coop::{Client, ClientMessage, Server, VisibleCell};
+use rust_sweeper::field::Field;
+use rust_sweeper::net;
+use rust_sweeper::sweeper::{Difficulty, GameState, Position, Sweeper};
+
+/// A 4x4 board with bombs in two corners, served on a free localhost port.
+fn server() -> (Server, String) {
+    let listener = net::listen("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let field = Field::from_bombs(4, 4, &[Position(0, 0, 0), Position(3, 3, 0)]);
+
+    (
+        Server::new(listener, Sweeper::from_field(Difficulty::Custom, field)).unwrap(),
+        addr,
+    )
+}
+
+/// Lets everything sent so far reach the server, be applied and come back.
+fn settle(server: &mut Server, clients: &mut [&mut Client]) {
+    for _ in 0..10 {
+        thread::sleep(Duration::from_millis(10));
+        server.step().unwrap();
+        for client in clients.iter_mut() {
+            client.poll().unwrap();
+        }
+    }
+}
+
+fn cell(client: &Client, row: usize, col: usize) -> VisibleCell {
+    client.board.as_ref().unwrap().cells[row][col]
+}
+
+#[test]
+fn test_players_see_each_other() {
+    let (mut server, addr) = server();
+    let mut alice = Client::connect(&addr).unwrap();
+    settle(&mut server, &mut [&mut alice]);
+    let mut bob = Client::connect(&addr).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+
+    assert_eq!((alice.id, bob.id), (Some(0), Some(1)));
+    assert_eq!(alice.cursors.len(), 2);
+
+    bob.send(&ClientMessage::Cursor(Position(3, 1, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert_eq!(alice.cursors[&1], Position(3, 1, 0));
+
+    drop(bob);
+    settle(&mut server, &mut [&mut alice]);
+    assert_eq!(alice.cursors.len(), 1);
+}
+
+#[test]
+fn test_conflicting_actions_resolve_by_player() {
+    let (mut server, addr) = server();
+    let mut alice = Client::connect(&addr).unwrap();
+    settle(&mut server, &mut [&mut alice]);
+    let mut bob = Client::connect(&addr).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+
+    // Bob sends first, but both arrive before the server steps: player 0 wins.
+    bob.send(&ClientMessage::Flag(Position(0, 0, 0))).unwrap();
+    alice.send(&ClientMessage::Flag(Position(0, 0, 0))).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert_eq!(cell(&bob, 0, 0), VisibleCell::Flag(Some(0)));
+
+    // Bob can neither take Alice's flag away nor reveal under it.
+    bob.send(&ClientMessage::Flag(Position(0, 0, 0))).unwrap();
+    bob.send(&ClientMessage::Reveal(Position(0, 0, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert_eq!(cell(&alice, 0, 0), VisibleCell::Flag(Some(0)));
+    assert_eq!(alice.board.as_ref().unwrap().state, GameState::Playing);
+
+    // Alice moves her flag on to a question mark, then takes it away.
+    alice.send(&ClientMessage::Flag(Position(0, 0, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert_eq!(cell(&bob, 0, 0), VisibleCell::Question);
+    bob.send(&ClientMessage::Flag(Position(0, 0, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert_eq!(cell(&bob, 0, 0), VisibleCell::Question);
+    alice.send(&ClientMessage::Flag(Position(0, 0, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert_eq!(cell(&bob, 0, 0), VisibleCell::Hidden);
+
+    bob.send(&ClientMessage::Reveal(Position(0, 1, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert!(matches!(cell(&alice, 0, 1), VisibleCell::Revealed(_)));
+
+    bob.send(&ClientMessage::Reveal(Position(3, 3, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert_eq!(alice.board.as_ref().unwrap().state, GameState::Lost);
+    assert_eq!(alice.board, bob.board);
+}
+
+#[test]
+fn test_leaving_gives_up_flags() {
+    let (mut server, addr) = server();
+    let mut alice = Client::connect(&addr).unwrap();
+    settle(&mut server, &mut [&mut alice]);
+    let mut bob = Client::connect(&addr).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+
+    bob.send(&ClientMessage::Flag(Position(0, 0, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut bob]);
+    assert_eq!(cell(&alice, 0, 0), VisibleCell::Flag(Some(1)));
+
+    // Whoever gets Bob's id next does not own his flag, Alice may move it on.
+    drop(bob);
+    settle(&mut server, &mut [&mut alice]);
+    assert_eq!(cell(&alice, 0, 0), VisibleCell::Flag(None));
+    let mut carol = Client::connect(&addr).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut carol]);
+    assert_eq!(carol.id, Some(1));
+    assert_eq!(cell(&carol, 0, 0), VisibleCell::Flag(None));
+
+    alice.send(&ClientMessage::Flag(Position(0, 0, 0))).unwrap();
+    settle(&mut server, &mut [&mut alice, &mut carol]);
+    assert_eq!(cell(&carol, 0, 0), VisibleCell::Question);
+}